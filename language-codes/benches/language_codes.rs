@@ -0,0 +1,105 @@
+//! Rough timings for the parts of this crate that are meant to be fast. Run
+//! them with `cargo bench`. They use a plain timing loop rather than a
+//! benchmarking framework, so the numbers are only good for comparing two
+//! approaches on the same machine.
+extern crate language_codes;
+
+use std::hint::black_box;
+use std::time::Instant;
+use language_codes::{languages, match_lists_with_cutoff, maximize_all, LanguageCode, TagInterner};
+
+const ITERATIONS: u32 = 100_000;
+
+/// A mix of tags like the ones that come up over and over in real data.
+const TAGS: &[&str] = &["en", "en-US", "zh-Hant-TW", "pt_BR", "iw", "sr-Latn", "es-419", "fr-CA"];
+
+/// Run `f` many times and print the average time it took.
+fn bench<F: FnMut()>(name: &str, mut f: F) {
+    // Run it once first, so that one-time setup isn't counted
+    f();
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        f();
+    }
+    let nanos = start.elapsed().as_nanos() / u128::from(ITERATIONS);
+    println!("{:<48} {:>8} ns/iter", name, nanos);
+}
+
+fn bench_interner() {
+    bench("parse, repeated tags", || {
+        for &tag in TAGS {
+            black_box(LanguageCode::parse(black_box(tag)).unwrap());
+        }
+    });
+    let mut interner = TagInterner::new();
+    bench("TagInterner::parse, repeated tags", || {
+        for &tag in TAGS {
+            black_box(interner.parse(black_box(tag)).unwrap());
+        }
+    });
+}
+
+fn bench_match_lists() {
+    // The exact match for the second desired language lets the search stop
+    // without trying the rest of the list.
+    let desired: Vec<LanguageCode> = ["qaa", "fr-CA", "de", "en"].iter()
+        .map(|tag| LanguageCode::parse(tag).unwrap())
+        .collect();
+    bench("match_lists_with_cutoff, all named languages", || {
+        black_box(match_lists_with_cutoff(5, 25, black_box(&desired), black_box(languages::ALL)));
+    });
+}
+
+fn bench_parse_two_letter() {
+    // Uppercase tags are valid but skip the fast path, so the difference
+    // between these is what the fast path saves.
+    let tags = ["en", "fr", "de", "es", "ja", "zh", "pt", "ru"];
+    let upper_tags = ["EN", "FR", "DE", "ES", "JA", "ZH", "PT", "RU"];
+    bench("parse, two-letter tags", || {
+        for &tag in &tags {
+            black_box(LanguageCode::parse(black_box(tag)).unwrap());
+        }
+    });
+    bench("parse, uppercase two-letter tags", || {
+        for &tag in &upper_tags {
+            black_box(LanguageCode::parse(black_box(tag)).unwrap());
+        }
+    });
+}
+
+fn bench_maximize_all() {
+    let codes: Vec<LanguageCode> = TAGS.iter().map(|tag| LanguageCode::parse(tag).unwrap()).collect();
+    bench("maximize, one at a time", || {
+        let maximized: Vec<LanguageCode> = black_box(&codes).iter().map(|code| code.maximize()).collect();
+        black_box(maximized);
+    });
+    bench("maximize_all", || {
+        black_box(maximize_all(black_box(&codes)));
+    });
+}
+
+/// Compare the allocating subtag getters with the `Cow` ones. Run this with
+/// `--features interned-subtags` to see the difference the feature makes.
+fn bench_subtags() {
+    let codes: Vec<LanguageCode> = TAGS.iter().map(|tag| LanguageCode::parse(tag).unwrap()).collect();
+    bench("get_script and get_region", || {
+        for code in black_box(&codes) {
+            black_box(code.get_script());
+            black_box(code.get_region());
+        }
+    });
+    bench("get_script_cow and get_region_cow", || {
+        for code in black_box(&codes) {
+            black_box(code.get_script_cow());
+            black_box(code.get_region_cow());
+        }
+    });
+}
+
+fn main() {
+    bench_interner();
+    bench_match_lists();
+    bench_parse_two_letter();
+    bench_maximize_all();
+    bench_subtags();
+}
@@ -3,14 +3,45 @@ extern crate language_tag_parser;
 extern crate json;
 
 use std::env;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::io::prelude::*;
 use std::io::{BufWriter, BufReader, Error};
 use std::fs::File;
-use language_tag_parser::{encode_tag, language_pair_bytes};
+use language_tag_parser::{encode_tag, language_pair_bytes, LANGUAGE_MASK};
+
+/// Find a data file. The tables are normally built from the CLDR data
+/// vendored in `data/`, but setting the environment variable
+/// `CLDR_DATA_DIR` to another directory builds them from the files there
+/// instead, such as a pre-release of CLDR. That directory must contain all
+/// of `aliases.json`, `likelySubtags.json`, `matching.txt`, and
+/// `languages.txt`.
+fn data_path(filename: &str) -> PathBuf {
+    let path = match env::var("CLDR_DATA_DIR") {
+        Ok(dir) => {
+            let path = Path::new(&dir).join(filename);
+            if !path.is_file() {
+                panic!("CLDR_DATA_DIR is set to {:?}, but {} is missing from it",
+                       dir, filename);
+            }
+            path
+        }
+        Err(_) => Path::new("data").join(filename),
+    };
+    println!("cargo:rerun-if-changed={}", path.display());
+    path
+}
+
+/// Check whether a letter is in one of the blocks that Unicode sets aside
+/// for right-to-left scripts. The letters in these blocks are all
+/// right-to-left in the Unicode bidi data.
+fn in_right_to_left_block(ch: char) -> bool {
+    matches!(ch as u32,
+             0x0590..=0x08FF | 0xFB1D..=0xFDFF | 0xFE70..=0xFEFF |
+             0x10800..=0x10FFF | 0x1E800..=0x1EFFF)
+}
 
 fn read_json(filename: &str) -> Result<json::JsonValue, Error> {
-    let mut f = File::open(filename)?;
+    let mut f = File::open(data_path(filename))?;
     let mut target_str = String::new();
     f.read_to_string(&mut target_str)?;
     Ok(json::parse(&target_str).unwrap())
@@ -20,7 +51,7 @@ fn make_tables() -> Result<(), Error> {
     let out_path = Path::new(&env::var("OUT_DIR").unwrap()).join("langdata.rs");
     let mut out_file = BufWriter::new(File::create(&out_path)?);
 
-    let parsed = read_json("data/aliases.json")?;
+    let parsed = read_json("aliases.json")?;
     let ref language_aliases = parsed["supplemental"]["metadata"]["alias"]["languageAlias"];
     let mut builder = phf_codegen::Map::new();
 
@@ -62,7 +93,8 @@ fn make_tables() -> Result<(), Error> {
         let replace_val = val["_replacement"].to_string();
         // Skip replacements with spaces; these indicate multiple
         // possibilities, such as replacing Yugoslavia with its
-        // successors. It is extremely unclear how to handle this case.
+        // successors. It is extremely unclear how to handle this case, so
+        // these go in REGION_SUCCESSORS instead.
         if !replace_val.contains(" ") {
             if key.len() == 2 || key.chars().nth(0).unwrap().is_digit(10) {
                 let replaced = encode_tag(&format!("und-{}", key)).unwrap();
@@ -74,7 +106,52 @@ fn make_tables() -> Result<(), Error> {
     builder.build(&mut out_file).unwrap();
     write!(&mut out_file, ";\n")?;
 
-    let parsed = read_json("data/likelySubtags.json")?;
+    // Regions with several successors, such as Yugoslavia, can't be
+    // replaced automatically, but we can list their successors.
+    let mut builder = phf_codegen::Map::new();
+    write!(&mut out_file,
+           "pub static REGION_SUCCESSORS: ::phf::Map<u64, &'static [u64]> = ")?;
+    for pair in region_aliases.entries() {
+        let (key, val) = pair;
+        let replace_val = val["_replacement"].to_string();
+        if replace_val.contains(" ") &&
+           (key.len() == 2 || key.starts_with(|c: char| c.is_ascii_digit())) {
+            let replaced = encode_tag(&format!("und-{}", key)).unwrap();
+            let successors: Vec<String> = replace_val.split(" ")
+                .map(|region| {
+                    let successor = encode_tag(&format!("und-{}", region)).unwrap();
+                    format!("{}", successor)
+                })
+                .collect();
+            builder.entry(replaced, &format!("&[{}]", successors.join(", ")));
+        }
+    }
+    builder.build(&mut out_file).unwrap();
+    writeln!(&mut out_file, ";")?;
+
+    // Make the reverse table, from alphabetic regions to their numeric
+    // M.49 codes, out of the "overlong" numeric aliases. A few alphabetic
+    // regions have a historical numeric code as well as the current one:
+    // 249 was "Metropolitan France", 278 was East Germany, and 720 was
+    // South Yemen. Those are skipped.
+    let historical_numeric = ["249", "278", "720"];
+    let mut builder = phf_codegen::Map::new();
+    write!(&mut out_file,
+           "pub static REGION_NUMERIC: ::phf::Map<u64, u64> = ")?;
+    for pair in region_aliases.entries() {
+        let (key, val) = pair;
+        let replace_val = val["_replacement"].to_string();
+        if key.starts_with(|c: char| c.is_ascii_digit()) && val["_reason"] == "overlong" &&
+           !replace_val.contains(" ") && !historical_numeric.contains(&key) {
+            let alpha = encode_tag(&format!("und-{}", replace_val)).unwrap();
+            let numeric = encode_tag(&format!("und-{}", key)).unwrap();
+            builder.entry(alpha, &numeric.to_string());
+        }
+    }
+    builder.build(&mut out_file).unwrap();
+    writeln!(&mut out_file, ";")?;
+
+    let parsed = read_json("likelySubtags.json")?;
     let ref likely_subtags = parsed["supplemental"]["likelySubtags"];
     let mut builder = phf_codegen::Map::new();
     write!(&mut out_file,
@@ -89,7 +166,7 @@ fn make_tables() -> Result<(), Error> {
     write!(&mut out_file, ";\n")?;
 
     // Read a file of language matches
-    let in_file = try!(File::open("data/matching.txt"));
+    let in_file = File::open(data_path("matching.txt"))?;
     let in_buf = BufReader::new(&in_file);
     let mut builder = phf_codegen::Map::new();
     write!(&mut out_file,
@@ -111,11 +188,180 @@ fn make_tables() -> Result<(), Error> {
     builder.build(&mut out_file).unwrap();
     write!(&mut out_file, ";\n")?;
 
+    // Read the ISO 15924 numeric codes for scripts. These don't come from
+    // CLDR, so they're always read from the vendored data.
+    let iso_path = Path::new("data").join("iso15924.txt");
+    println!("cargo:rerun-if-changed={}", iso_path.display());
+    let in_file = File::open(&iso_path)?;
+    let in_buf = BufReader::new(&in_file);
+    let mut builder = phf_codegen::Map::new();
+    write!(&mut out_file,
+           "pub static SCRIPT_NUMERIC: ::phf::Map<u64, u16> = ")?;
+    for line_w in in_buf.lines() {
+        let line = line_w?;
+        let parts: Vec<&str> = line.split("\t").collect();
+        let script = encode_tag(&format!("und-{}", parts[0])).unwrap();
+        let numeric: u16 = parts[1].parse().unwrap();
+        builder.entry(script, &numeric.to_string());
+    }
+    builder.build(&mut out_file).unwrap();
+    writeln!(&mut out_file, ";")?;
+
+    // Read the stable small IDs for common languages. Like the script
+    // numbers, these are our own data, so they're always read from data/.
+    // IDs must count up from 1 with no gaps, so that they can index a list.
+    // The file is append-only: a new language gets the next unused ID at the
+    // end, and existing lines never change, so the IDs stay stable.
+    let ids_path = Path::new("data").join("common_languages.txt");
+    println!("cargo:rerun-if-changed={}", ids_path.display());
+    let in_file = File::open(&ids_path)?;
+    let in_buf = BufReader::new(&in_file);
+    let mut builder = phf_codegen::Map::new();
+    let mut common_codes: Vec<u64> = Vec::new();
+    let mut common_names: Vec<String> = Vec::new();
+    write!(&mut out_file,
+           "pub static COMMON_LANGUAGE_IDS: ::phf::Map<u64, u16> = ")?;
+    for line_w in in_buf.lines() {
+        let line = line_w?;
+        let parts: Vec<&str> = line.split("\t").collect();
+        let id: u16 = parts[0].parse().unwrap();
+        let language = encode_tag(parts[1]).unwrap();
+        if id as usize != common_codes.len() + 1 {
+            panic!("common_languages.txt: expected ID {}, found {}",
+                   common_codes.len() + 1, id);
+        }
+        builder.entry(language, &id.to_string());
+        common_codes.push(language);
+        common_names.push(parts[1].to_string());
+    }
+    builder.build(&mut out_file).unwrap();
+    writeln!(&mut out_file, ";")?;
+    writeln!(&mut out_file, "pub static COMMON_LANGUAGES: [u64; {}] = [", common_codes.len())?;
+    for code in common_codes {
+        writeln!(&mut out_file, "    0x{:>016x}_u64,", code)?;
+    }
+    writeln!(&mut out_file, "];")?;
+
+    // Read the layout properties of scripts, which we curate by hand from
+    // CLDR's scriptMetadata.txt. The columns are the script, a sample letter
+    // of it, then YES or NO for whether it's written right-to-left, has case,
+    // and requires shaping. The direction and case are checked against the
+    // sample letter, so a mistake in either column stops the build.
+    let metadata_path = Path::new("data").join("script_metadata.txt");
+    println!("cargo:rerun-if-changed={}", metadata_path.display());
+    let in_file = File::open(&metadata_path)?;
+    let in_buf = BufReader::new(&in_file);
+    let mut builder = phf_codegen::Map::new();
+    write!(&mut out_file,
+           "pub static SCRIPT_PROPERTIES: ::phf::Map<u64, ::ScriptProperties> = ")?;
+    for line_w in in_buf.lines() {
+        let line = line_w?;
+        let parts: Vec<&str> = line.split("\t").collect();
+        let script = encode_tag(&format!("und-{}", parts[0])).unwrap();
+        let sample: char = parts[1].chars().next().unwrap();
+        for value in &parts[2..] {
+            if *value != "YES" && *value != "NO" {
+                panic!("script_metadata.txt: expected YES or NO for {}, found {}", parts[0], value);
+            }
+        }
+        let yes = |column: usize| parts[column] == "YES";
+        if yes(2) != in_right_to_left_block(sample) {
+            panic!("script_metadata.txt: the direction of {} doesn't match its sample {}",
+                   parts[0], sample);
+        }
+        if yes(3) != (sample.is_uppercase() || sample.is_lowercase()) {
+            panic!("script_metadata.txt: the case of {} doesn't match its sample {}",
+                   parts[0], sample);
+        }
+        builder.entry(script, &format!("::ScriptProperties {{ rtl: {}, cased: {}, requires_shaping: {} }}",
+                                       yes(2), yes(3), yes(4)));
+    }
+    builder.build(&mut out_file).unwrap();
+    writeln!(&mut out_file, ";")?;
+
+    // Read the groups of scripts that are written together. These are our
+    // own data, based on the ISO 15924 codes for combinations of scripts.
+    let groups_path = Path::new("data").join("script_groups.txt");
+    println!("cargo:rerun-if-changed={}", groups_path.display());
+    let in_file = File::open(&groups_path)?;
+    let in_buf = BufReader::new(&in_file);
+    writeln!(&mut out_file, "pub static SCRIPT_GROUPS: &[&[u64]] = &[")?;
+    for line_w in in_buf.lines() {
+        let line = line_w?;
+        let scripts: Vec<String> = line.split("\t")
+            .map(|script| format!("0x{:>016x}_u64", encode_tag(&format!("und-{}", script)).unwrap()))
+            .collect();
+        writeln!(&mut out_file, "    &[{}],", scripts.join(", "))?;
+    }
+    writeln!(&mut out_file, "];")?;
+
+    // Read the coarse language families, which are also our own data.
+    let families_path = Path::new("data").join("families.txt");
+    println!("cargo:rerun-if-changed={}", families_path.display());
+    let in_file = File::open(&families_path)?;
+    let in_buf = BufReader::new(&in_file);
+    let mut builder = phf_codegen::Map::new();
+    write!(&mut out_file,
+           "pub static LANGUAGE_FAMILIES: ::phf::Map<u64, ::Family> = ")?;
+    for line_w in in_buf.lines() {
+        let line = line_w?;
+        let parts: Vec<&str> = line.split("\t").collect();
+        let language = encode_tag(parts[0]).unwrap();
+        builder.entry(language, &format!("::Family::{}", parts[1]));
+    }
+    builder.build(&mut out_file).unwrap();
+    writeln!(&mut out_file, ";")?;
+
+    // Make a table of the strings for the most common subtags, so that they
+    // can be returned without allocating: the common languages, and every
+    // script and region that appears in the likely-subtags data or has an
+    // ISO 15924 number. Subtags of different kinds are encoded in different
+    // bits, so they can share a table.
+    let mut interned: Vec<(u64, String)> = Vec::new();
+    for name in common_names {
+        interned.push((encode_tag(&name).unwrap(), name));
+    }
+    let mut script_names: Vec<String> = Vec::new();
+    for line_w in BufReader::new(File::open(&iso_path)?).lines() {
+        let line = line_w?;
+        script_names.push(line.split("\t").next().unwrap().to_string());
+    }
+    let mut region_names: Vec<String> = Vec::new();
+    for pair in likely_subtags.entries() {
+        let (key, val) = pair;
+        let val = val.to_string();
+        for subtag in key.split("-").skip(1).chain(val.split("-").skip(1)) {
+            if subtag.len() == 4 {
+                script_names.push(subtag.to_string());
+            } else {
+                region_names.push(subtag.to_string());
+            }
+        }
+    }
+    for name in script_names.into_iter().chain(region_names) {
+        let code = encode_tag(&format!("und-{}", name)).unwrap();
+        if code != 0 {
+            interned.push((code, name));
+        }
+    }
+    interned.sort();
+    interned.dedup_by_key(|&mut (code, _)| code);
+    let mut builder = phf_codegen::Map::new();
+    write!(&mut out_file,
+           "pub static SUBTAG_STRS: ::phf::Map<u64, &'static str> = ")?;
+    for (code, name) in interned {
+        builder.entry(code, &format!("{:?}", name));
+    }
+    builder.build(&mut out_file).unwrap();
+    writeln!(&mut out_file, ";")?;
+
     // Now write a convenient file of constants for commonly-used languages.
     let const_path = Path::new(&env::var("OUT_DIR").unwrap()).join("languages.rs");
     let mut const_file = BufWriter::new(File::create(&const_path)?);
-    let in_file = try!(File::open("data/languages.txt"));
+    let in_file = File::open(data_path("languages.txt"))?;
     let in_buf = BufReader::new(&in_file);
+    let mut all_names: Vec<String> = Vec::new();
+    let mut all_codes: Vec<u64> = Vec::new();
     for line_w in in_buf.lines() {
         let line = line_w?;
         let parts: Vec<&str> = line.split("\t").collect();
@@ -125,11 +371,25 @@ fn make_tables() -> Result<(), Error> {
                "pub const {:<24}: LanguageCode = LanguageCode {{ data: 0x{:>016x}_u64 }};\n",
                from_name,
                to_code)?;
+        // Collect each distinct code that names a language, skipping
+        // synonyms such as BANGLA for BENGALI
+        if to_code & LANGUAGE_MASK != 0 && !all_codes.contains(&to_code) {
+            all_names.push(from_name.to_string());
+            all_codes.push(to_code);
+        }
+    }
+    write!(&mut const_file, "\n/// Every distinct language code that has a constant in this module.\n")?;
+    writeln!(&mut const_file, "pub const ALL: &[LanguageCode] = &[")?;
+    for name in all_names {
+        writeln!(&mut const_file, "    {},", name)?;
     }
+    writeln!(&mut const_file, "];")?;
 
     Ok(())
 }
 
 fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-env-changed=CLDR_DATA_DIR");
     make_tables().unwrap();
 }
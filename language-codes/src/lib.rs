@@ -1,29 +1,249 @@
 #[macro_use]
 extern crate phf;
 extern crate language_tag_parser;
+#[cfg(feature = "serde")]
+extern crate serde;
+#[cfg(all(test, feature = "serde-compact"))]
+extern crate bincode;
+#[cfg(all(test, feature = "serde"))]
+extern crate serde_json;
 
+use std::borrow::Cow;
+use std::cmp;
 use std::str::FromStr;
 use std::fmt;
-pub use language_tag_parser::{LanguageCodeError, encode_tag, decode_tag, decode_language,
-                              decode_extlang, decode_script, decode_region, update_code,
-                              language_pair_bytes, LANGUAGE_MASK, LANGUAGE_EXT_MASK, SCRIPT_MASK,
-                              REGION_MASK, INHERIT_SCRIPT, INHERIT_SCRIPT_OLD, EMPTY_CODE};
+use std::io;
+use std::io::BufRead;
+use std::collections::{HashMap, HashSet};
+use language_tag_parser::{is_region_subtag, is_script_subtag};
+#[cfg(feature = "serde")]
+use serde::{Serialize, Deserialize};
+pub use language_tag_parser::{LanguageCodeError, encode_tag, decode_tag, write_tag, decode_language,
+                              decode_extlang, decode_script, decode_region, encode_language,
+                              encode_script, encode_region, update_code,
+                              language_pair_bytes, split_pair_bytes, classify_subtags, SubtagRole, LANGUAGE_MASK, LANGUAGE_EXT_MASK, PROTO_MASK,
+                              EXTLANG_MASK, SCRIPT_MASK,
+                              REGION_MASK, INHERIT_SCRIPT, INHERIT_SCRIPT_OLD, EMPTY_CODE,
+                              MISSING_CODE};
 pub mod langdata;
 pub mod languages;
 
 const SIMPLIFIED: u64 = languages::SIMPLIFIED_CHINESE.data & SCRIPT_MASK;
 const TRADITIONAL: u64 = languages::TRADITIONAL_CHINESE.data & SCRIPT_MASK;
 
+/// Match distances up to this value are minor variations, such as between
+/// regional varieties of a language.
+pub const MATCH_DISTANCE_MINOR: i32 = 10;
+
+/// Match distances up to this value should still be comprehensible, if
+/// potentially unsatisfying to the user. This is the default cutoff for
+/// matching.
+pub const MATCH_DISTANCE_COMPREHENSIBLE: i32 = 25;
+
+/// The match distance between completely unrelated languages.
+pub const MATCH_DISTANCE_UNRELATED: i32 = 124;
+
+/// The match distance between `mul` (multiple languages) and any single
+/// language. It's beyond the usual cutoffs, but a cutoff above it lets
+/// multilingual content serve as a last resort.
+pub const MATCH_DISTANCE_MULTIPLE: i32 = 100;
+
+/// The CLDR default for a code that specifies nothing at all: "und"
+/// maximizes to "en-Latn-US".
+const ROOT_MAXIMIZED: u64 = languages::AMERICAN_ENGLISH.data | languages::LATIN_SCRIPT.data;
+
 /// A LanguageCode is a wrapper around a 64-bit integer, so don't worry
 /// about copying them around. Think of this as a big enum.
-#[derive(PartialEq, Debug, Clone, Copy)]
+#[derive(PartialEq, Eq, Hash, Debug, Clone, Copy)]
 pub struct LanguageCode {
     data: u64,
 }
 
+/// The subtags of a language code, decoded as separate strings. With the
+/// `serde` feature, this serializes as an object such as
+/// `{"language":"zh","script":"Hant","region":"TW"}`, leaving out the subtags
+/// that are None.
+#[derive(PartialEq, Eq, Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct LanguageParts {
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "Option::is_none"))]
+    pub language: Option<String>,
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "Option::is_none"))]
+    pub extlang: Option<String>,
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "Option::is_none"))]
+    pub script: Option<String>,
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "Option::is_none"))]
+    pub region: Option<String>,
+}
+
 impl fmt::Display for LanguageCode {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "lang(\"{}\")", self.to_string())
+        self.fmt_canonical(f)
+    }
+}
+
+/// A language subtag on its own, such as `fr`, validated when it's parsed.
+/// Converting it to a LanguageCode gives a code with only the language, and
+/// converting a LanguageCode to it takes the language and nothing else.
+/// `und` is the unknown language.
+#[derive(PartialEq, Eq, Hash, Debug, Clone, Copy)]
+pub struct Language(u64);
+
+/// A coarse grouping of languages, for summarizing them in reports. Most of
+/// these are language families, but some are branches of a family, such as
+/// Romance and Slavic, and a few aren't families at all, such as Creole and
+/// Constructed. Take it as a convenience, not as linguistics.
+#[derive(PartialEq, Eq, Hash, Debug, Clone, Copy)]
+pub enum Family {
+    Albanian,
+    Algonquian,
+    Armenian,
+    AustroAsiatic,
+    Austronesian,
+    Aymaran,
+    Baltic,
+    Berber,
+    Caucasian,
+    Celtic,
+    Chadic,
+    Constructed,
+    Creole,
+    Cushitic,
+    Dravidian,
+    Egyptian,
+    EskimoAleut,
+    Germanic,
+    Hellenic,
+    HmongMien,
+    IndoIranian,
+    Iroquoian,
+    /// A language with no known relatives, such as Basque
+    Isolate,
+    Japonic,
+    Kartvelian,
+    Khoe,
+    Koreanic,
+    MacroJe,
+    Mayan,
+    Mongolic,
+    Muskogean,
+    NaDene,
+    NigerCongo,
+    NiloSaharan,
+    Omotic,
+    Otomanguean,
+    Quechuan,
+    Romance,
+    Semitic,
+    Sinitic,
+    Siouan,
+    Slavic,
+    TaiKadai,
+    TibetoBurman,
+    Tupian,
+    Turkic,
+    Uralic,
+    UtoAztecan,
+}
+
+/// Properties of a script that affect how text in it is laid out, from
+/// `LanguageCode::script_properties`.
+#[derive(PartialEq, Eq, Hash, Debug, Clone, Copy)]
+pub struct ScriptProperties {
+    /// The script is written right-to-left
+    pub rtl: bool,
+    /// The script distinguishes uppercase and lowercase letters
+    pub cased: bool,
+    /// Letters change shape depending on their neighbors, as in Arabic, so
+    /// the text needs a shaping engine to display correctly
+    pub requires_shaping: bool,
+}
+
+/// A script subtag on its own, such as `Cyrl`, validated when it's parsed.
+/// `Zzzz` is the unknown script.
+#[derive(PartialEq, Eq, Hash, Debug, Clone, Copy)]
+pub struct Script(u64);
+
+/// A region subtag on its own, such as `BR` or `419`, validated when it's
+/// parsed. `ZZ` is the unknown region.
+#[derive(PartialEq, Eq, Hash, Debug, Clone, Copy)]
+pub struct Region(u64);
+
+impl FromStr for Language {
+    type Err = LanguageCodeError;
+
+    fn from_str(s: &str) -> Result<Language, LanguageCodeError> {
+        Ok(Language(encode_language(s)?))
+    }
+}
+
+impl FromStr for Script {
+    type Err = LanguageCodeError;
+
+    fn from_str(s: &str) -> Result<Script, LanguageCodeError> {
+        Ok(Script(encode_script(s)?))
+    }
+}
+
+impl FromStr for Region {
+    type Err = LanguageCodeError;
+
+    fn from_str(s: &str) -> Result<Region, LanguageCodeError> {
+        Ok(Region(encode_region(s)?))
+    }
+}
+
+impl fmt::Display for Language {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&decode_language(self.0))
+    }
+}
+
+impl fmt::Display for Script {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&decode_script(self.0).unwrap_or_else(|| "Zzzz".to_string()))
+    }
+}
+
+impl fmt::Display for Region {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&decode_region(self.0).unwrap_or_else(|| "ZZ".to_string()))
+    }
+}
+
+impl From<LanguageCode> for Language {
+    fn from(code: LanguageCode) -> Language {
+        Language(code.data & LANGUAGE_MASK)
+    }
+}
+
+impl From<LanguageCode> for Script {
+    fn from(code: LanguageCode) -> Script {
+        Script(code.data & SCRIPT_MASK)
+    }
+}
+
+impl From<LanguageCode> for Region {
+    fn from(code: LanguageCode) -> Region {
+        Region(code.data & REGION_MASK)
+    }
+}
+
+impl From<Language> for LanguageCode {
+    fn from(language: Language) -> LanguageCode {
+        LanguageCode::new(language.0)
+    }
+}
+
+impl From<Script> for LanguageCode {
+    fn from(script: Script) -> LanguageCode {
+        LanguageCode::new(script.0)
+    }
+}
+
+impl From<Region> for LanguageCode {
+    fn from(region: Region) -> LanguageCode {
+        LanguageCode::new(region.0)
     }
 }
 
@@ -38,6 +258,42 @@ impl LanguageCode {
         decode_language(self.data)
     }
 
+    /// Get the language subtag, as in `language_subtag`, but without
+    /// allocating a String when the language is unknown. With the
+    /// `interned-subtags` feature, common languages don't allocate either.
+    pub fn language_subtag_cow(self) -> Cow<'static, str> {
+        if self.data & LANGUAGE_MASK == 0 {
+            Cow::Borrowed("und")
+        } else {
+            interned_subtag(self.data & LANGUAGE_MASK)
+                .unwrap_or_else(|| Cow::Owned(decode_language(self.data)))
+        }
+    }
+
+    /// Get the script subtag, as in `get_script`. With the
+    /// `interned-subtags` feature, this only allocates for rare scripts.
+    pub fn get_script_cow(&self) -> Option<Cow<'static, str>> {
+        interned_subtag(self.data & SCRIPT_MASK)
+            .or_else(|| decode_script(self.data).map(Cow::Owned))
+    }
+
+    /// Get the region subtag, as in `get_region`. With the
+    /// `interned-subtags` feature, this only allocates for rare regions.
+    pub fn get_region_cow(&self) -> Option<Cow<'static, str>> {
+        interned_subtag(self.data & REGION_MASK)
+            .or_else(|| decode_region(self.data).map(Cow::Owned))
+    }
+
+    /// Check whether this code's language subtag is the given subtag, such as
+    /// "en", ignoring case. This compares the encoded values, so it doesn't
+    /// allocate anything. "und" matches codes whose language is unknown.
+    pub fn language_is(self, subtag: &str) -> bool {
+        match encode_language(subtag) {
+            Ok(lang_val) => self.data & LANGUAGE_MASK == lang_val,
+            Err(_) => false,
+        }
+    }
+
     /// Get the 2- or 3-character language code as an Option<String>, giving
     /// None if the language is unknown.
     pub fn get_language(self) -> Option<String> {
@@ -52,6 +308,15 @@ impl LanguageCode {
         decode_extlang(self.data)
     }
 
+    /// Get the extlang subtag as a LanguageCode that contains only the
+    /// extlang, including whether it marks a proto-language, or None if
+    /// there is no extlang. This lets you compare and group extlangs without
+    /// decoding them into strings.
+    pub fn extlang_code(self) -> Option<LanguageCode> {
+        let extlang: u64 = self.data & (PROTO_MASK | EXTLANG_MASK);
+        if extlang == 0 { None } else { Some(LanguageCode::new(extlang)) }
+    }
+
     /// Remove information about script and region from this language code,
     /// leaving a code that only distinguishes the language itself. This can
     /// be useful in a match statement in NLP applications that only need
@@ -62,6 +327,194 @@ impl LanguageCode {
         LanguageCode { data: self.data & LANGUAGE_EXT_MASK }
     }
 
+    /// Remove information about language and script from this language code,
+    /// leaving a code such as `und-TW` that only specifies the region.
+    pub fn region_only(self) -> LanguageCode {
+        LanguageCode { data: self.data & REGION_MASK }
+    }
+
+    /// Remove information about language and region from this language code,
+    /// leaving a code such as `und-Hant` that only specifies the script.
+    pub fn script_only(self) -> LanguageCode {
+        LanguageCode { data: self.data & SCRIPT_MASK }
+    }
+
+    /// Get the ISO 15924 numeric code for this code's script, such as 215 for
+    /// `Latn`. Returns None if there is no script, or if it's a script we
+    /// don't have a number for.
+    pub fn script_numeric(self) -> Option<u16> {
+        langdata::SCRIPT_NUMERIC.get(&(self.data & SCRIPT_MASK)).cloned()
+    }
+
+    /// Get the layout properties of this code's script, such as whether it's
+    /// written right-to-left. These come from `data/script_metadata.txt`,
+    /// which follows CLDR's script metadata. Returns None if there is no
+    /// script, or if it's a script we don't have properties for, including
+    /// variants such as `Latf` and special codes such as `Zyyy`.
+    pub fn script_properties(self) -> Option<ScriptProperties> {
+        langdata::SCRIPT_PROPERTIES.get(&(self.data & SCRIPT_MASK)).cloned()
+    }
+
+    /// Get this code with its region replaced by the equivalent numeric UN
+    /// M.49 region, such as `en-840` for `en-US`, so that regions can be
+    /// compared the same way whether they were given as letters or numbers.
+    /// A code whose region is already numeric, such as `es-419`, is returned
+    /// as it is. Returns None if there is no region, or it has no numeric
+    /// equivalent.
+    ///
+    /// `parse` would turn the result back into the alphabetic form, so it's
+    /// not canonical, and it's not meant for display.
+    pub fn region_as_numeric_code(self) -> Option<LanguageCode> {
+        let region: u64 = self.data & REGION_MASK;
+        if region == 0 {
+            return None;
+        }
+        if let Some(&numeric) = langdata::REGION_NUMERIC.get(&region) {
+            return Some(LanguageCode::new(update_code(self.data, numeric)));
+        }
+        match decode_region(region) {
+            Some(ref name) if name.as_bytes()[0].is_ascii_digit() => Some(self),
+            _ => None,
+        }
+    }
+
+    /// Assign this code to one of `num_shards` shards, based only on its
+    /// language, so that `en-US` and `en-GB` go to the same shard. The hash
+    /// is fixed, so the same language always gets the same shard, across
+    /// runs and versions of this crate. Panics if `num_shards` is 0.
+    pub fn language_shard(self, num_shards: u32) -> u32 {
+        // This is the finalizer from SplitMix64, applied to the language
+        // bits with a fixed seed.
+        let mut hash: u64 = (self.data & LANGUAGE_EXT_MASK) ^ 0x9e37_79b9_7f4a_7c15_u64;
+        hash = (hash ^ (hash >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9_u64);
+        hash = (hash ^ (hash >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb_u64);
+        hash ^= hash >> 31;
+        (hash % num_shards as u64) as u32
+    }
+
+    /// Get the coarse family of this code's language, such as
+    /// `Family::Romance` for `fr`, from the table in `data/families.txt`.
+    /// This covers the languages that have a `common_language_id`; other
+    /// languages get None.
+    pub fn language_family(self) -> Option<Family> {
+        langdata::LANGUAGE_FAMILIES.get(&(self.data & LANGUAGE_MASK)).cloned()
+    }
+
+    /// Get a small, stable ID for this code's language, for interfaces such
+    /// as C enums where a 64-bit code is unwieldy. Only the language counts:
+    /// `zh-Hant-TW` has the same ID as `zh`. The IDs are listed in
+    /// `data/common_languages.txt`, which covers about 500 common languages;
+    /// other languages get None. IDs start at 1, and are never reused or
+    /// renumbered.
+    pub fn common_language_id(self) -> Option<u16> {
+        langdata::COMMON_LANGUAGE_IDS.get(&(self.data & LANGUAGE_MASK)).cloned()
+    }
+
+    /// Get the language-only code with the given ID from
+    /// `common_language_id`, or None if no language has that ID.
+    pub fn from_common_language_id(id: u16) -> Option<LanguageCode> {
+        match id {
+            0 => None,
+            _ => langdata::COMMON_LANGUAGES.get(id as usize - 1).map(|&val| LanguageCode::new(val)),
+        }
+    }
+
+    /// Get the codes that result from replacing this code's region with each
+    /// region that succeeded it, such as `hu-RS` and `hu-ME` for `hu-YU`.
+    /// `parse` replaces regions that have one successor, such as `DD`, but
+    /// leaves regions with several successors alone, so that you can decide
+    /// what to do with them. The result is empty if the region has no
+    /// successors.
+    pub fn region_successors(self) -> Vec<LanguageCode> {
+        let region: u64 = self.data & REGION_MASK;
+        let successors: &[u64] = match langdata::REGION_SUCCESSORS.get(&region) {
+            Some(&successors) => successors,
+            None => match langdata::REGION_REPLACE.get(&region) {
+                Some(successor) => std::slice::from_ref(successor),
+                None => &[],
+            },
+        };
+        successors.iter()
+            .map(|&successor| LanguageCode::new((self.data & !REGION_MASK) | successor))
+            .collect()
+    }
+
+    /// Is this the root locale, the ultimate fallback in CLDR? The root
+    /// locale is written as "root" or "und", and specifies nothing.
+    pub fn is_root(self) -> bool {
+        self.data == EMPTY_CODE
+    }
+
+    /// Is this `zxx`, the code for content with no linguistic content, such
+    /// as instrumental music? It never matches a real language.
+    pub fn is_no_content(self) -> bool {
+        self.data & LANGUAGE_EXT_MASK == languages::NO_LINGUISTIC_CONTENT.data
+    }
+
+    /// Is this `mul`, the code for content in multiple languages?
+    pub fn is_multiple(self) -> bool {
+        self.data & LANGUAGE_EXT_MASK == languages::MULTIPLE_LANGUAGES.data
+    }
+
+    /// Does this code name a specific human language? This is false for the
+    /// codes that are valid but extralinguistic -- `und`, `mis`, `mul`, and
+    /// `zxx` -- and for private-use codes, meaning the languages `qaa`
+    /// through `qtz` and tags such as `x-klingon`.
+    pub fn is_specific_language(self) -> bool {
+        let language: u64 = self.data & LANGUAGE_MASK;
+        if language == EMPTY_CODE || self.data == MISSING_CODE ||
+           language == languages::UNCODED_LANGUAGE.data ||
+           self.is_multiple() || self.is_no_content() {
+            return false;
+        }
+        let subtag: String = decode_language(self.data);
+        !(subtag.len() == 3 && subtag.as_str() >= "qaa" && subtag.as_str() <= "qtz")
+    }
+
+    /// Is this `art`, the collective code for constructed (artificial)
+    /// languages? Such tags are often followed by a private-use subtag, as
+    /// in `art-x-elvish`, but the private-use part isn't kept.
+    pub fn is_constructed(self) -> bool {
+        self.data & LANGUAGE_EXT_MASK == languages::CONSTRUCTED_LANGUAGE.data
+    }
+
+    /// Get a copy of this code with its language subtag replaced, which also
+    /// removes any extlang. Replacing it with "und" leaves the language
+    /// unspecified.
+    pub fn with_language(self, language: &str) -> Result<LanguageCode, LanguageCodeError> {
+        let lang_val: u64 = encode_language(language)?;
+        Ok(LanguageCode::new((self.data & !LANGUAGE_EXT_MASK) | lang_val))
+    }
+
+    /// Get a copy of this code with its script subtag replaced. Replacing it
+    /// with "Zzzz" leaves the script unspecified.
+    pub fn with_script(self, script: &str) -> Result<LanguageCode, LanguageCodeError> {
+        let script_val: u64 = encode_script(script)?;
+        Ok(LanguageCode::new((self.data & !SCRIPT_MASK) | script_val))
+    }
+
+    /// Get a copy of this code with its region subtag replaced. Replacing it
+    /// with "ZZ" leaves the region unspecified.
+    pub fn with_region(self, region: &str) -> Result<LanguageCode, LanguageCodeError> {
+        let region_val: u64 = encode_region(region)?;
+        Ok(LanguageCode::new((self.data & !REGION_MASK) | region_val))
+    }
+
+    /// Get a copy of this code with the language (and extlang) removed.
+    pub fn without_language(self) -> LanguageCode {
+        LanguageCode::new(self.data & !LANGUAGE_EXT_MASK)
+    }
+
+    /// Get a copy of this code with the script removed.
+    pub fn without_script(self) -> LanguageCode {
+        LanguageCode::new(self.data & !SCRIPT_MASK)
+    }
+
+    /// Get a copy of this code with the region removed.
+    pub fn without_region(self) -> LanguageCode {
+        LanguageCode::new(self.data & !REGION_MASK)
+    }
+
     /// Get the 4-character script code as an Option<String>, giving None
     /// if the script is unset. This returns None in the case of an implicit
     /// script: that is, the script of code `en` is `None`, not `Some("Latn")`.
@@ -76,60 +529,271 @@ impl LanguageCode {
         decode_region(self.data)
     }
 
-    pub fn to_string(&self) -> String {
-        decode_tag(self.data)
+    /// Write the string form of this code, such as "zh-Hant-TW", directly to
+    /// a formatter, without building an intermediate String. This is how
+    /// LanguageCodes are displayed, and `to_string()` gives the same result.
+    pub fn fmt_canonical(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write_tag(f, self.data)
     }
 
     pub fn parse(tag: &str) -> Result<LanguageCode, LanguageCodeError> {
+        // Fast path: most tags in the wild are just two lowercase letters,
+        // like "en". If there's no alias for them, encode them directly.
+        let bytes: &[u8] = tag.as_bytes();
+        if bytes.len() == 2 && bytes[0].is_ascii_lowercase() && bytes[1].is_ascii_lowercase() {
+            let val: u64 = encode_language(tag)?;
+            if !langdata::LANG_REPLACE.contains_key(&val) &&
+               !langdata::TAG_REPLACE.contains_key(tag) {
+                return Ok(LanguageCode::new(val));
+            }
+        }
+        LanguageCode::parse_general(tag)
+    }
+
+    /// Parse a tag without the fast path for two-letter tags.
+    fn parse_general(tag: &str) -> Result<LanguageCode, LanguageCodeError> {
         let normal_tag: String = tag.replace("_", "-").to_lowercase();
         match langdata::TAG_REPLACE.get(&normal_tag as &str) {
             Some(&repl) => Ok(LanguageCode::new(repl)),
             None => {
-                let mut val: u64 = encode_tag(tag)?;
-                let lang_val: u64 = val & LANGUAGE_MASK;
-                match langdata::LANG_REPLACE.get(&lang_val) {
-                    Some(&newlang) => {
-                        // We got a new language code for this language, and
-                        // need to merge it with what else we know. When both
-                        // the old and new tag provide a subtag, keep the new
-                        // value for the language subtag, or the old value for
-                        // any other subtag.
-                        val = update_code(update_code(val, newlang), val & !LANGUAGE_EXT_MASK);
-                    }
-                    None => {}
-                }
+                let val: u64 = encode_tag(tag)?;
+                Ok(LanguageCode::new(replace_aliases(val)))
+            }
+        }
+    }
 
-                // The only script replacement is Qaai -> Zinh.
-                // (I don't even know when you would use this.)
-                let script_val: u64 = val & SCRIPT_MASK;
-                if script_val == INHERIT_SCRIPT_OLD {
-                    val = update_code(val, INHERIT_SCRIPT);
-                }
+    /// Check whether parsing this code's string form gives back the same
+    /// code. Codes from `parse` should always be canonical, but codes from
+    /// `parse_raw` or `new` may have deprecated subtags.
+    pub fn is_canonical(self) -> bool {
+        LanguageCode::parse(&self.to_string()) == Ok(self)
+    }
 
-                let region_val: u64 = val & REGION_MASK;
-                match langdata::REGION_REPLACE.get(&region_val) {
-                    Some(&newregion) => {
-                        val = update_code(val, newregion);
-                    }
-                    None => {}
+    /// Get the string form of this code with the script left out, even if
+    /// it was added by `maximize()`, for systems that can't handle script
+    /// subtags. Unlike `minimize()`, this always keeps the region.
+    pub fn to_string_without_script(self) -> String {
+        self.without_script().to_string()
+    }
+
+    /// Get a form of this code that's safe for URLs and filenames, such as
+    /// "zh_hant_tw": the subtags in lowercase, joined with underscores.
+    pub fn to_slug(self) -> String {
+        self.to_string().to_ascii_lowercase().replace("-", "_")
+    }
+
+    /// Parse a slug from `to_slug`. This is the same as `parse`, which
+    /// already accepts underscores and any case.
+    pub fn from_slug(slug: &str) -> Result<LanguageCode, LanguageCodeError> {
+        LanguageCode::parse(slug)
+    }
+
+    /// Parse a language tag without replacing deprecated subtags with their
+    /// current equivalents, so that "iw" stays "iw" instead of becoming
+    /// "he". This is the same as `parse`, minus the alias tables.
+    pub fn parse_raw(tag: &str) -> Result<LanguageCode, LanguageCodeError> {
+        Ok(LanguageCode::new(encode_tag(tag)?))
+    }
+
+    /// Parse a language tag, also accepting tags that put the script after
+    /// the region, such as "en-US-Latn", which `parse` rejects. Returns the
+    /// code and whether the subtags had to be reordered, so that callers can
+    /// warn about the malformed tag.
+    pub fn parse_tolerant(tag: &str) -> Result<(LanguageCode, bool), LanguageCodeError> {
+        let err = match LanguageCode::parse(tag) {
+            Ok(code) => return Ok((code, false)),
+            Err(err) => err,
+        };
+        let mut subtags: Vec<&str> = tag.split(&['-', '_'][..]).collect();
+        for index in 1..subtags.len().saturating_sub(1) {
+            if is_region_subtag(subtags[index]) && is_script_subtag(subtags[index + 1]) {
+                subtags.swap(index, index + 1);
+                return match LanguageCode::parse(&subtags.join("-")) {
+                    Ok(code) => Ok((code, true)),
+                    Err(_) => Err(err),
+                };
+            }
+        }
+        Err(err)
+    }
+
+    /// Parse a language tag that may be wrapped as `lang("en-US")`, which is
+    /// how older versions of this crate displayed LanguageCodes. This helps
+    /// recover codes from old log lines. Plain tags are parsed as in `parse`.
+    pub fn parse_debug_form(s: &str) -> Result<LanguageCode, LanguageCodeError> {
+        let trimmed: &str = s.trim();
+        if trimmed.starts_with("lang(\"") && trimmed.ends_with("\")") && trimmed.len() >= 8 {
+            LanguageCode::parse(&trimmed[6..trimmed.len() - 2])
+        } else {
+            LanguageCode::parse(trimmed)
+        }
+    }
+
+    /// Parse a tag that overrides some of this code's subtags, keeping this
+    /// code's subtags wherever the tag doesn't give one. For example,
+    /// `lang("en-Latn-US").parse_onto("fr")` gives `fr-Latn-US`.
+    ///
+    /// The tag can leave out the language, either by starting with a
+    /// separator, as in "-GB" or "-Cyrl-RS", or by starting with a subtag
+    /// that can't be a language: a script such as "Cyrl", a numeric region
+    /// such as "419", or an uppercase region such as "GB". A bare lowercase
+    /// two-letter subtag, such as "gb", is read as a language.
+    pub fn parse_onto(self, tag: &str) -> Result<LanguageCode, LanguageCodeError> {
+        let separators = &['-', '_'][..];
+        let trimmed: &str = tag.trim_start_matches(separators);
+        if trimmed.is_empty() {
+            return Ok(self);
+        }
+        let first: &str = trimmed.split(separators).next().unwrap_or("");
+        let has_language: bool = trimmed.len() == tag.len() &&
+                                 first.len() != 4 &&
+                                 !first.bytes().all(|b| b.is_ascii_digit()) &&
+                                 !(first.len() == 2 && first.bytes().all(|b| b.is_ascii_uppercase()));
+        let overrides: LanguageCode = if has_language {
+            LanguageCode::parse(trimmed)?
+        } else {
+            LanguageCode::parse(&format!("und-{}", trimmed))?
+        };
+        Ok(LanguageCode::new(update_code(self.data, overrides.data)))
+    }
+
+    /// Parse a language tag, also returning the part that a LanguageCode
+    /// doesn't store: everything from the first extension or private-use
+    /// singleton onward, exactly as it appeared in the tag. For example,
+    /// "en-US-u-ca-gregory" gives `en-US` and "u-ca-gregory".
+    pub fn parse_with_remainder(tag: &str)
+                                -> Result<(LanguageCode, Option<String>), LanguageCodeError> {
+        let mut start: usize = 0;
+        for (index, subtag) in tag.split(&['-', '_'][..]).enumerate() {
+            if index > 0 && subtag.len() == 1 && subtag.as_bytes()[0].is_ascii_alphanumeric() {
+                let code = LanguageCode::parse(&tag[..start - 1])?;
+                return Ok((code, Some(tag[start..].to_string())));
+            }
+            start += subtag.len() + 1;
+        }
+        Ok((LanguageCode::parse(tag)?, None))
+    }
+
+    /// Check whether two codes mean the same thing once deprecated subtags
+    /// are replaced, as `parse` would replace them. This differs from `==`
+    /// when either code came from `parse_raw`: `parse_raw("iw")` is not
+    /// equal to `lang("he")`, but it is equivalent.
+    pub fn is_equivalent(self, other: LanguageCode) -> bool {
+        self.replace_deprecated() == other.replace_deprecated()
+    }
+
+    /// Check whether this code is a deprecated form of `other`: it differs
+    /// from `other`, but replacing its deprecated subtags gives `other`. For
+    /// example, `parse_raw("iw")` is a deprecated form of `lang("he")`. A
+    /// code is not a deprecated form of itself.
+    pub fn is_deprecated_form_of(self, other: LanguageCode) -> bool {
+        self != other && self.replace_deprecated() == other
+    }
+
+    /// Apply the replacements that `parse` makes to a code that may not have
+    /// gone through them.
+    fn replace_deprecated(self) -> LanguageCode {
+        LanguageCode::parse(&self.to_string()).unwrap_or(self)
+    }
+
+    /// Assemble a LanguageCode from its language subtag and optional script
+    /// and region subtags, checking that each one has the right shape. The
+    /// same alias replacements are applied as in `parse`, so
+    /// `from_parts("iw", None, Some("IL"))` gives `he-IL`.
+    pub fn from_parts(language: &str,
+                      script: Option<&str>,
+                      region: Option<&str>)
+                      -> Result<LanguageCode, LanguageCodeError> {
+        let mut val: u64 = encode_language(language)?;
+        if let Some(script) = script {
+            val |= encode_script(script)?;
+        }
+        if let Some(region) = region {
+            val |= encode_region(region)?;
+        }
+        let normal_tag: String = decode_tag(val).to_lowercase();
+        match langdata::TAG_REPLACE.get(&normal_tag as &str) {
+            Some(&repl) => Ok(LanguageCode::new(repl)),
+            None => Ok(LanguageCode::new(replace_aliases(val))),
+        }
+    }
+
+    /// Decode all the subtags of this code into a `LanguageParts` struct.
+    pub fn to_parts(self) -> LanguageParts {
+        LanguageParts {
+            language: self.get_language(),
+            extlang: self.get_extlang(),
+            script: self.get_script(),
+            region: self.get_region(),
+        }
+    }
+
+    /// Build a LanguageCode from a `LanguageParts` struct, checking the shape
+    /// of each subtag and replacing deprecated values as in `from_parts`.
+    pub fn from_parts_struct(parts: &LanguageParts) -> Result<LanguageCode, LanguageCodeError> {
+        let language: &str = parts.language.as_ref().map_or("und", |s| s);
+        let code = LanguageCode::from_parts(language,
+                                            parts.script.as_ref().map(|s| s as &str),
+                                            parts.region.as_ref().map(|s| s as &str))?;
+        match parts.extlang {
+            Some(ref extlang) => {
+                let extlang_val: u64 = encode_tag(&format!("und-{}", extlang))?;
+                if extlang_val & !(PROTO_MASK | EXTLANG_MASK) != 0 {
+                    return Err(LanguageCodeError::SubtagFormatError(extlang.to_string()));
                 }
-                Ok(LanguageCode::new(val))
+                Ok(LanguageCode::new(code.data | extlang_val))
             }
+            None => Ok(code),
         }
     }
 
     /// Get a sequence of more general versions of this code.
     pub fn broaden(self) -> Vec<LanguageCode> {
-        let possibilities = vec![self.data & (LANGUAGE_MASK | SCRIPT_MASK | REGION_MASK),
-                                 self.data & (LANGUAGE_MASK | REGION_MASK),
-                                 self.data & (LANGUAGE_MASK | SCRIPT_MASK),
-                                 self.data & LANGUAGE_MASK,
-                                 self.data & REGION_MASK,
-                                 self.data & SCRIPT_MASK,
-                                 EMPTY_CODE];
+        let mut broader: Vec<LanguageCode> = Vec::with_capacity(7);
+        self.broaden_into(&mut broader);
+        broader
+    }
+
+    /// Replace the contents of `out` with the result of `broaden()`, so that
+    /// a buffer can be reused.
+    fn broaden_into(self, out: &mut Vec<LanguageCode>) {
+        let possibilities = [self.data & (LANGUAGE_MASK | SCRIPT_MASK | REGION_MASK),
+                             self.data & (LANGUAGE_MASK | REGION_MASK),
+                             self.data & (LANGUAGE_MASK | SCRIPT_MASK),
+                             self.data & LANGUAGE_MASK,
+                             self.data & REGION_MASK,
+                             self.data & SCRIPT_MASK,
+                             EMPTY_CODE];
+        out.clear();
         // Skip codes that are equal to the input
-        let filtered = possibilities.into_iter().filter(|&n| n != self.data);
-        filtered.map(|val| LanguageCode::new(val)).collect()
+        let filtered = possibilities.iter().filter(|&&n| n != self.data);
+        out.extend(filtered.map(|&val| LanguageCode::new(val)));
+    }
+
+    /// Fill in the subtags that this code leaves unspecified from another
+    /// code, keeping this code's subtags where both have them. For example,
+    /// merging `en-Latn` with `und-GB` gives `en-Latn-GB`, but merging `en-US`
+    /// with `und-GB` keeps `en-US`.
+    pub fn merge(self, other: LanguageCode) -> LanguageCode {
+        LanguageCode::new(update_code(other.data, self.data))
+    }
+
+    /// Get the most specific code that broadens to both this code and
+    /// another, by keeping the subtags they have in common. For example, the
+    /// common ancestor of `zh-Hant-TW` and `zh-Hant-HK` is `zh-Hant`, and
+    /// the common ancestor of `en` and `fr` is `und`.
+    pub fn common_ancestor(self, other: LanguageCode) -> LanguageCode {
+        let mut val: u64 = EMPTY_CODE;
+        for &mask in [LANGUAGE_MASK, SCRIPT_MASK, REGION_MASK].iter() {
+            if self.data & mask == other.data & mask {
+                val |= self.data & mask;
+            }
+        }
+        // An extlang is only in common if the language is
+        if self.data & LANGUAGE_EXT_MASK == other.data & LANGUAGE_EXT_MASK {
+            val |= self.data & LANGUAGE_EXT_MASK;
+        }
+        LanguageCode::new(val)
     }
 
     /// Get a code with a language, region, and script, filling in the most
@@ -137,10 +801,21 @@ impl LanguageCode {
     /// "pt" maximizes to "pt-Latn-BR". This is the "maximize" or "add likely
     /// subtags" operation defined in UTS #35.
     pub fn maximize(self) -> Self {
+        self.maximize_with(ROOT_MAXIMIZED, &mut Vec::new())
+    }
+
+    /// Maximize this code, using `buffer` as scratch space for broadening it.
+    /// `root` is the maximized form of the root locale, which fills in
+    /// whatever the likely-subtags data doesn't.
+    fn maximize_with(self, root: u64, buffer: &mut Vec<LanguageCode>) -> Self {
         if (self.data & LANGUAGE_MASK != 0) && (self.data & SCRIPT_MASK != 0) &&
            (self.data & REGION_MASK != 0) {
             // We can tell this code is already maximal.
             return self;
+        } else if self.data == EMPTY_CODE {
+            // The root locale maximizes to the CLDR default, whether or not
+            // the likely-subtags data has an entry for it.
+            LanguageCode::new(root)
         } else {
             match langdata::LIKELY_SUBTAGS.get(&self.data) {
                 Some(&max) => {
@@ -148,7 +823,8 @@ impl LanguageCode {
                 }
                 None => {}
             }
-            for broader_code in self.broaden() {
+            self.broaden_into(buffer);
+            for broader_code in buffer.iter().filter(|code| code.data != EMPTY_CODE) {
                 match langdata::LIKELY_SUBTAGS.get(&broader_code.data) {
                     Some(&max) => {
                         return LanguageCode::new(update_code(max, self.data));
@@ -156,7 +832,53 @@ impl LanguageCode {
                     None => {}
                 }
             }
-            panic!("I'm missing data about how to maximize language codes");
+            // Every code broadens to the root locale eventually, so fill in
+            // the remaining fields from its default.
+            LanguageCode::new(update_code(root, self.data))
+        }
+    }
+
+    /// Check whether choosing a region can change the script that this
+    /// code's language maximizes to. This is true for `zh`, which is `Hans`
+    /// in `CN` but `Hant` in `TW`, and false for `en`, which is `Latn`
+    /// everywhere. Only the language of this code is considered.
+    pub fn region_affects_script(self) -> bool {
+        let language: u64 = self.data & LANGUAGE_EXT_MASK;
+        let default_script: u64 = LanguageCode::new(language).maximize().data & SCRIPT_MASK;
+        // A region can only change the result of maximize() through a
+        // likely-subtags entry that has a region, for this language or
+        // for an undetermined one.
+        langdata::LIKELY_SUBTAGS.keys().any(|&key| {
+            let region: u64 = key & REGION_MASK;
+            let key_language: u64 = key & LANGUAGE_EXT_MASK;
+            region != 0 && (key_language == language || key_language == 0) &&
+            LanguageCode::new(language | region).maximize().data & SCRIPT_MASK != default_script
+        })
+    }
+
+    /// Guess the script of this code as a script-only code, such as `und-Cyrl`,
+    /// for when the script can't be inferred any other way.
+    ///
+    /// This is a heuristic, separate from `maximize()`. When the code has a
+    /// script, or the likely-subtags data can infer one without falling back
+    /// on the root locale, you get that script. Otherwise, `zxx` gets `Zxxx`
+    /// (unwritten), a language with no script data gets `Zyyy` (common), and
+    /// a code with no language gets None. `maximize()` would instead fill in
+    /// `Latn` from the root locale.
+    pub fn script_fallback(self) -> Option<LanguageCode> {
+        if self.data & SCRIPT_MASK != 0 {
+            return Some(self.script_only());
+        }
+        let known = Some(self).into_iter()
+            .chain(self.broaden())
+            .filter(|code| !code.is_root())
+            .filter_map(|code| langdata::LIKELY_SUBTAGS.get(&code.data).cloned())
+            .next();
+        match known {
+            Some(max) => Some(LanguageCode::new(max).script_only()),
+            None if self.is_no_content() => Some(languages::UNWRITTEN_SCRIPT),
+            None if self.data & LANGUAGE_MASK != 0 => Some(languages::COMMON_SCRIPT),
+            None => None,
         }
     }
 
@@ -167,7 +889,20 @@ impl LanguageCode {
     /// returning un-normalized tags (zh-TW is aliased to zh-Hans-TW anyway),
     /// and is more symmetric with `maximize()`.
     pub fn minimize(self) -> Self {
+        self.minimize_from(self.maximize())
+    }
+
+    /// Get both the minimal and maximal forms of this code, as in
+    /// `(self.minimize(), self.maximize())`, maximizing it only once. This
+    /// is handy for caching a form for display alongside a form for
+    /// matching.
+    pub fn normalize_pair(self) -> (LanguageCode, LanguageCode) {
         let max = self.maximize();
+        (self.minimize_from(max), max)
+    }
+
+    /// Minimize this code, given its maximized form `max`.
+    fn minimize_from(self, max: LanguageCode) -> Self {
         let possibilities = vec![self.data & LANGUAGE_MASK,
                                  self.data & (LANGUAGE_MASK | SCRIPT_MASK),
                                  self.data & (LANGUAGE_MASK | REGION_MASK)];
@@ -199,13 +934,20 @@ impl LanguageCode {
     /// Get the distance between two maximized language codes,
     /// disregarding the region (which has already been checked)
     /// and comparing them at the script level.
-    fn match_distance_script(self, other: LanguageCode) -> i32 {
+    fn match_distance_script(self, other: LanguageCode, config: &MatcherConfig) -> i32 {
         let lang1: u64 = self.data & LANGUAGE_EXT_MASK;
         let lang2: u64 = other.data & LANGUAGE_EXT_MASK;
         let script1: u64 = self.data & SCRIPT_MASK;
         let script2: u64 = other.data & SCRIPT_MASK;
+        let script_pair = (LanguageCode::new(lang1 | script1), LanguageCode::new(lang2 | script2));
         if (lang1 | script1) == (lang2 | script2) {
             0
+        } else if script1 != script2 && config.forbid_script_mismatch {
+            1000
+        } else if let Some(&dist) = config.script_distances.get(&script_pair) {
+            dist
+        } else if config.symmetric_cjk && is_chinese_script_pair(script1, script2) {
+            19 + self.match_distance_language(other)
         } else if script1 == script2 {
             // When the scripts are the same, go on to matching the language.
             // We can check this first because there's nothing in matching.txt
@@ -238,7 +980,7 @@ impl LanguageCode {
     /// distance for the language/script/region triples, or we'll
     /// compute a distance for just the region part, and pass the rest
     /// to `match_distance_script`.
-    fn match_distance_region(self, other: LanguageCode) -> i32 {
+    fn match_distance_region(self, other: LanguageCode, config: &MatcherConfig) -> i32 {
         if self.data == other.data {
             // These codes are the same, so the distance is exactly 0.
             0
@@ -259,7 +1001,7 @@ impl LanguageCode {
                     if region1 == region2 {
                         // If the regions are the same, the region adds 0 distance.
                         // Return just the distance from `match_distance_script()`.
-                        self.match_distance_script(other)
+                        self.match_distance_script(other, config)
                     } else {
                         // There are several wildcard rules that match at the region
                         // level, and the following code implements them (instead of
@@ -283,12 +1025,12 @@ impl LanguageCode {
                             // a distance of 8.
                             if lang_region1 == languages::BRAZILIAN_PORTUGUESE.data ||
                                lang_region2 == languages::BRAZILIAN_PORTUGUESE.data {
-                                8 + self.match_distance_script(other)
+                                8 + self.match_distance_script(other, config)
                             } else if lang_region1 == languages::AMERICAN_PORTUGUESE.data ||
                                       lang_region2 == languages::AMERICAN_PORTUGUESE.data {
-                                8 + self.match_distance_script(other)
+                                8 + self.match_distance_script(other, config)
                             } else {
-                                4 + self.match_distance_script(other)
+                                4 + self.match_distance_script(other, config)
                             }
                         } else if lang1 == languages::ENGLISH.data &&
                                   lang2 == languages::ENGLISH.data {
@@ -298,15 +1040,15 @@ impl LanguageCode {
                             // English is farther away from all of these.
                             if lang_region1 == languages::AMERICAN_ENGLISH.data ||
                                lang_region2 == languages::AMERICAN_ENGLISH.data {
-                                6 + self.match_distance_script(other)
+                                6 + self.match_distance_script(other, config)
                             } else if lang_region1 == languages::BRITISH_ENGLISH.data ||
                                       lang_region2 == languages::BRITISH_ENGLISH.data {
-                                4 + self.match_distance_script(other)
+                                4 + self.match_distance_script(other, config)
                             } else if lang_region1 == languages::INTERNATIONAL_ENGLISH.data ||
                                       lang_region2 == languages::INTERNATIONAL_ENGLISH.data {
-                                4 + self.match_distance_script(other)
+                                4 + self.match_distance_script(other, config)
                             } else {
-                                5 + self.match_distance_script(other)
+                                5 + self.match_distance_script(other, config)
                             }
                         } else if lang1 == languages::SPANISH.data &&
                                   lang2 == languages::SPANISH.data {
@@ -316,17 +1058,17 @@ impl LanguageCode {
                             // but es-ES.
                             if lang_region1 == languages::EUROPEAN_SPANISH.data ||
                                lang_region2 == languages::EUROPEAN_SPANISH.data {
-                                8 + self.match_distance_script(other)
+                                8 + self.match_distance_script(other, config)
                             } else if lang_region1 == languages::LATIN_AMERICAN_SPANISH.data ||
                                       lang_region2 == languages::LATIN_AMERICAN_SPANISH.data {
-                                4 + self.match_distance_script(other)
+                                4 + self.match_distance_script(other, config)
                             } else {
-                                5 + self.match_distance_script(other)
+                                5 + self.match_distance_script(other, config)
                             }
                         } else {
                             // In languages with no specific wildcard rules, a difference in
                             // region only adds 4 distance.
-                            4 + self.match_distance_script(other)
+                            4 + self.match_distance_script(other, config)
                         }
                     }
                 }
@@ -337,108 +1079,1021 @@ impl LanguageCode {
     /// Return a number representing the distance between this language
     /// code (the desired language) and another (the supported language).
     ///
-    /// A distance of 0 indicates an exact match. Distances up to 10 are
-    /// minor variations, and distances up to 20 or 25 should still be
-    /// comprehensible, if potentially unsatisfying to the user.
-    /// The distance between completely unrelated languages is 124.
+    /// A distance of 0 indicates an exact match. Distances up to 10
+    /// (`MATCH_DISTANCE_MINOR`) are minor variations, and distances up to 20
+    /// or 25 (`MATCH_DISTANCE_COMPREHENSIBLE`) should still be comprehensible,
+    /// if potentially unsatisfying to the user. The distance between
+    /// completely unrelated languages is 124 (`MATCH_DISTANCE_UNRELATED`),
+    /// which is also the distance between `zxx` and any real language. The
+    /// distance between `mul` and any single language is 100
+    /// (`MATCH_DISTANCE_MULTIPLE`). A code is always at distance 0 from
+    /// itself, whether or not it's maximal.
     pub fn match_distance(self, other: LanguageCode) -> i32 {
-        self.maximize().match_distance_region(other.maximize())
+        if self == other {
+            // Skip maximizing: identical codes maximize identically.
+            return 0;
+        }
+        MatcherConfig::default().match_distance(self, other)
+    }
+
+    /// Find the languages in `languages::ALL` that are within `max_distance`
+    /// of this one, sorted from closest to farthest. Match distances aren't
+    /// always symmetric -- people who speak `mg` can often read `fr`, but
+    /// not the other way around -- so this uses the smaller distance of the
+    /// two directions. This code itself is not included.
+    pub fn related_languages(self, max_distance: i32) -> Vec<(LanguageCode, i32)> {
+        let mut related: Vec<(LanguageCode, i32)> = languages::ALL.iter()
+            .filter(|&&other| other != self)
+            .map(|&other| {
+                (other, cmp::min(self.match_distance(other), other.match_distance(self)))
+            })
+            .filter(|&(_, distance)| distance <= max_distance)
+            .collect();
+        related.sort_by_key(|&(other, distance)| (distance, other.data));
+        related
+    }
+
+    /// Get the distance between just the languages of this code (the desired
+    /// language) and another (the supported language), ignoring their
+    /// scripts and regions. For example, `ms` to `id` is 10, even though
+    /// their full codes differ in region. Languages that CLDR doesn't
+    /// relate are 80 apart at this level.
+    pub fn language_distance(self, other: LanguageCode) -> i32 {
+        self.language_only().match_distance_language(other.language_only())
+    }
+
+    /// Get the same distance as `match_distance`, for codes that are already
+    /// maximized, such as codes that were maximized once and stored in an
+    /// index. This skips maximizing them again. In debug builds, it panics
+    /// if either code isn't maximal.
+    pub fn match_distance_premaximized(self, other: LanguageCode) -> i32 {
+        debug_assert!(self.maximize() == self, "{} is not maximized", self);
+        debug_assert!(other.maximize() == other, "{} is not maximized", other);
+        MatcherConfig::default().match_distance_maximized(self, other)
     }
 
     pub fn find_match(self,
                       rank_penalty: i32,
                       cutoff: i32,
-                      possibilities: &Vec<LanguageCode>)
+                      possibilities: &[LanguageCode])
                       -> (LanguageCode, i32) {
-        let mut rank_cost: i32 = 0;
-        let mut best_match: LanguageCode = languages::UNKNOWN;
-        let mut best_distance: i32 = 1000;
-        let mut best_cost: i32 = 1000;
-
-        for &other in possibilities {
-            let distance: i32 = self.match_distance(other);
-            let cost: i32 = distance + rank_cost;
-            if distance == 0 {
-                return (other, 0);
-            }
-            if distance < cutoff && cost < best_cost {
-                best_match = other;
-                best_cost = cost;
-                best_distance = distance;
-            }
-            rank_cost += rank_penalty;
-            if rank_cost >= best_cost {
-                break;
-            }
-        }
-        (best_match, best_distance)
+        MatcherConfig::default().find_match(self, rank_penalty, cutoff, possibilities)
     }
 
     pub fn match_desired_with_cutoff(self,
                                      cutoff: i32,
-                                     desired: &Vec<LanguageCode>)
+                                     desired: &[LanguageCode])
                                      -> (LanguageCode, i32) {
         self.find_match(5, cutoff, desired)
     }
 
-    pub fn match_desired(self, desired: &Vec<LanguageCode>) -> (LanguageCode, i32) {
-        self.find_match(5, 25, desired)
+    pub fn match_desired(self, desired: &[LanguageCode]) -> (LanguageCode, i32) {
+        self.find_match(5, MATCH_DISTANCE_COMPREHENSIBLE, desired)
     }
 
     pub fn match_supported_with_cutoff(self,
                                        cutoff: i32,
-                                       supported: &Vec<LanguageCode>)
+                                       supported: &[LanguageCode])
                                        -> (LanguageCode, i32) {
-        for &other in supported {
-            if other == self {
-                return (other, 0);
-            }
-        }
-        self.find_match(0, cutoff, supported)
+        MatcherConfig::default().match_supported_with_cutoff(self, cutoff, supported)
     }
 
-    pub fn match_supported(self, supported: &Vec<LanguageCode>) -> (LanguageCode, i32) {
-        self.find_match(0, 25, supported)
+    pub fn match_supported(self, supported: &[LanguageCode]) -> (LanguageCode, i32) {
+        self.find_match(0, MATCH_DISTANCE_COMPREHENSIBLE, supported)
     }
-}
 
+    /// Check whether some supported language matches this desired language
+    /// with a distance less than `cutoff`. `MATCH_DISTANCE_COMPREHENSIBLE`,
+    /// which the other matching methods use by default, is a reasonable
+    /// cutoff for whether a user is served acceptably.
+    pub fn is_adequately_supported(self, supported: &[LanguageCode], cutoff: i32) -> bool {
+        self.match_supported_with_cutoff(cutoff, supported).1 < cutoff
+    }
 
-pub fn match_lists_with_cutoff(rank_penalty: i32,
-                               cutoff: i32,
-                               desired: &Vec<LanguageCode>,
-                               supported: &Vec<LanguageCode>)
-                               -> (LanguageCode, i32) {
-    let mut rank_cost: i32 = 0;
-    let mut best_match: LanguageCode = languages::UNKNOWN;
-    let mut best_distance: i32 = 1000;
-    let mut best_cost: i32 = 1000;
-    for &d in desired {
-        let (matched, distance) = d.match_supported_with_cutoff(cutoff, supported);
-        let cost: i32 = distance + rank_cost;
-        if distance < cutoff && cost < best_cost {
-            best_match = d;
-            best_cost = cost;
-            best_distance = distance;
-        }
-        rank_cost += rank_penalty;
-        if rank_cost >= best_cost {
-            break;
+    /// Find the best match for this language among the supported languages,
+    /// as in `match_supported`, and return a reference to it within the
+    /// `supported` slice, or None if nothing matches.
+    pub fn match_supported_ref(self,
+                               supported: &[LanguageCode])
+                               -> Option<(&LanguageCode, i32)> {
+        MatcherConfig::default()
+            .find_match_index(self, 0, MATCH_DISTANCE_COMPREHENSIBLE, supported)
+            .map(|(index, distance)| (&supported[index], distance))
+    }
+
+    /// Find the best match for this language among the supported languages,
+    /// falling back on a match for the language alone if nothing matches
+    /// within the cutoff. Returns the match and its real distance from this
+    /// language, or None if even the language alone doesn't match.
+    ///
+    /// The first stage is `match_supported_with_cutoff`, so CLDR's rules for
+    /// regional varieties apply: `es-AR` prefers `es-419` to `es-ES`. If that
+    /// finds nothing, the fallback ignores regions: it chooses the supported
+    /// language that would be closest if it were spoken in the desired
+    /// region, breaking ties by the real distance. Every region of the
+    /// language is equally good to the fallback, and the distance it returns
+    /// is at or beyond the cutoff, so compare the distance to the cutoff to
+    /// tell whether the fallback was used.
+    ///
+    /// This is not widening through CLDR's region containment (such as AR ->
+    /// 005 -> 419), which would need containment data that we don't have.
+    pub fn match_supported_with_language_fallback(self,
+                                                  supported: &[LanguageCode],
+                                                  cutoff: i32)
+                                                  -> Option<(LanguageCode, i32)> {
+        let (matched, distance) = self.match_supported_with_cutoff(cutoff, supported);
+        if distance < cutoff {
+            return Some((matched, distance));
         }
+        let desired = self.maximize();
+        supported.iter()
+            .map(|&other| {
+                let other_region = other.maximize().data & REGION_MASK;
+                let widened = LanguageCode::new((desired.data & !REGION_MASK) | other_region);
+                (other, widened.match_distance(other), self.match_distance(other))
+            })
+            .filter(|&(_, widened_distance, _)| widened_distance < cutoff)
+            .min_by_key(|&(_, widened_distance, distance)| (widened_distance, distance))
+            .map(|(other, _, distance)| (other, distance))
     }
-    (best_match, best_distance)
 }
 
 
-impl FromStr for LanguageCode {
-    type Err = LanguageCodeError;
-
-    /// Parse a LanguageCode from its string representation. The result
-    /// is a constant-sized Struct that encodes its language, script, and
-    /// region.
-    fn from_str(s: &str) -> Result<LanguageCode, LanguageCodeError> {
-        LanguageCode::parse(&s)
+/// Apply the replacements for deprecated language, script, and region
+/// subtags to an encoded tag.
+fn replace_aliases(code: u64) -> u64 {
+    let mut val: u64 = code;
+    let lang_val: u64 = val & LANGUAGE_MASK;
+    match langdata::LANG_REPLACE.get(&lang_val) {
+        Some(&newlang) => {
+            // We got a new language code for this language, and
+            // need to merge it with what else we know. When both
+            // the old and new tag provide a subtag, keep the new
+            // value for the language subtag, or the old value for
+            // any other subtag. This is how an alias such as
+            // sh -> sr-Latn can change the script that the code
+            // maximizes to.
+            val = update_code(update_code(val, newlang), val & !LANGUAGE_EXT_MASK);
+        }
+        None => {}
     }
-}
+
+    // The only script replacement is Qaai -> Zinh.
+    // (I don't even know when you would use this.)
+    let script_val: u64 = val & SCRIPT_MASK;
+    if script_val == INHERIT_SCRIPT_OLD {
+        val = update_code(val, INHERIT_SCRIPT);
+    }
+
+    // CLDR lists numeric regions with a single alphabetic equivalent, such
+    // as 840 for US, as "overlong" aliases, so they get replaced here.
+    // Regions that only exist as numbers, such as 419, are left alone.
+    let region_val: u64 = val & REGION_MASK;
+    match langdata::REGION_REPLACE.get(&region_val) {
+        Some(&newregion) => {
+            val = update_code(val, newregion);
+        }
+        None => {}
+    }
+    val
+}
+
+/// Look up the string for a single encoded subtag in the table of common
+/// subtags.
+#[cfg(feature = "interned-subtags")]
+fn interned_subtag(subtag: u64) -> Option<Cow<'static, str>> {
+    langdata::SUBTAG_STRS.get(&subtag).map(|&name| Cow::Borrowed(name))
+}
+
+#[cfg(not(feature = "interned-subtags"))]
+fn interned_subtag(_subtag: u64) -> Option<Cow<'static, str>> {
+    None
+}
+
+/// Replace Norwegian or Nynorsk with Bokmål, for `norwegian_unified`.
+fn unify_norwegian(code: LanguageCode) -> LanguageCode {
+    let language: u64 = code.data & LANGUAGE_EXT_MASK;
+    if language == languages::NORWEGIAN.data || language == languages::NYNORSK.data {
+        LanguageCode::new(update_code(code.data, languages::NORWEGIAN_BOKMAL.data))
+    } else {
+        code
+    }
+}
+
+/// Options that change how language codes are matched. The default options
+/// follow CLDR's language matching rules, and are what the matching methods
+/// on `LanguageCode` use.
+#[derive(Clone, Debug)]
+pub struct MatcherConfig {
+    /// Never match languages in different scripts, such as `sr-Cyrl` and
+    /// `sr-Latn`. Their distance becomes 1000, which is beyond any cutoff.
+    pub forbid_script_mismatch: bool,
+
+    /// Treat Norwegian (`no`), Bokmål (`nb`) and Nynorsk (`nn`) as the same
+    /// language, so the distance between any two of them is 0. Otherwise,
+    /// `nb` and `nn` are 10 apart.
+    pub norwegian_unified: bool,
+
+    /// Regions to assume for desired codes that don't specify one, keyed by
+    /// language. For example, mapping `en` to `en-GB` makes a request for
+    /// bare `en` act like a request for `en-GB`, instead of `en-US` as in
+    /// CLDR. Only the region of each value is used. This doesn't affect
+    /// supported codes.
+    pub default_regions: HashMap<LanguageCode, LanguageCode>,
+
+    /// Distances to use between two scripts of the same language, replacing
+    /// the distances from CLDR. The keys are pairs of desired and supported
+    /// codes with a language and script and no region, such as
+    /// `(sr-Latn, sr-Cyrl)`. Each override only applies in the direction it
+    /// is given.
+    pub script_distances: HashMap<(LanguageCode, LanguageCode), i32>,
+
+    /// Use the same distance in both directions between Simplified (`Hans`)
+    /// and Traditional (`Hant`) Chinese characters. CLDR puts Hans -> Hant
+    /// at 15 and Hant -> Hans at 19; with this option, both are 19.
+    pub symmetric_cjk: bool,
+
+    /// What to assume for codes with no language, such as `und`, when the
+    /// likely-subtags data doesn't say anything more specific than the root
+    /// locale. For example, `es` makes `und` maximize to `es-Latn-ES`
+    /// instead of `en-Latn-US`. Codes such as `und-RU`, which CLDR can
+    /// resolve to a language, are not affected. The default is CLDR's,
+    /// `en-Latn-US`.
+    pub root_default: LanguageCode,
+}
+
+impl Default for MatcherConfig {
+    fn default() -> MatcherConfig {
+        MatcherConfig {
+            forbid_script_mismatch: false,
+            norwegian_unified: false,
+            default_regions: HashMap::new(),
+            script_distances: HashMap::new(),
+            symmetric_cjk: false,
+            root_default: LanguageCode::new(ROOT_MAXIMIZED),
+        }
+    }
+}
+
+impl MatcherConfig {
+    pub fn new() -> MatcherConfig {
+        MatcherConfig::default()
+    }
+
+    /// Get the distance between a desired language and a supported
+    /// language, as in `LanguageCode::match_distance`, using these options.
+    pub fn match_distance(&self, desired: LanguageCode, supported: LanguageCode) -> i32 {
+        let desired = self.apply_default_region(desired);
+        if self.norwegian_unified {
+            self.match_distance_maximized(self.maximize(unify_norwegian(desired)),
+                                          self.maximize(unify_norwegian(supported)))
+        } else {
+            self.match_distance_maximized(self.maximize(desired), self.maximize(supported))
+        }
+    }
+
+    /// Maximize a code, as in `LanguageCode::maximize`, using `root_default`
+    /// for codes with no language.
+    pub fn maximize(&self, code: LanguageCode) -> LanguageCode {
+        if code.data & LANGUAGE_MASK == 0 {
+            code.maximize_with(self.root_default.maximize().data, &mut Vec::new())
+        } else {
+            code.maximize()
+        }
+    }
+
+    /// Fill in the region from `default_regions` for a desired code that
+    /// has none.
+    fn apply_default_region(&self, desired: LanguageCode) -> LanguageCode {
+        if desired.data & REGION_MASK != 0 {
+            return desired;
+        }
+        match self.default_regions.get(&desired.language_only()) {
+            Some(region) => {
+                LanguageCode::new(desired.data | (region.data & REGION_MASK))
+            }
+            None => desired,
+        }
+    }
+
+    /// Get the distance between two codes that have already been maximized.
+    fn match_distance_maximized(&self, desired: LanguageCode, supported: LanguageCode) -> i32 {
+        if desired.is_no_content() != supported.is_no_content() {
+            // `zxx` can only be matched by asking for `zxx`.
+            return MATCH_DISTANCE_UNRELATED;
+        }
+        if desired.is_multiple() != supported.is_multiple() {
+            return MATCH_DISTANCE_MULTIPLE;
+        }
+        desired.match_distance_region(supported, self)
+    }
+
+    /// Find the best match for a desired language among some possibilities,
+    /// as in `LanguageCode::find_match`, using these options.
+    pub fn find_match(&self,
+                      desired: LanguageCode,
+                      rank_penalty: i32,
+                      cutoff: i32,
+                      possibilities: &[LanguageCode])
+                      -> (LanguageCode, i32) {
+        match self.find_match_index(desired, rank_penalty, cutoff, possibilities) {
+            Some((index, distance)) => (possibilities[index], distance),
+            None => (languages::UNKNOWN, 1000),
+        }
+    }
+
+    /// Find the position of the best match in `possibilities`, and its
+    /// distance, or None if nothing is within the cutoff.
+    fn find_match_index(&self,
+                        desired: LanguageCode,
+                        rank_penalty: i32,
+                        cutoff: i32,
+                        possibilities: &[LanguageCode])
+                        -> Option<(usize, i32)> {
+        let mut rank_cost: i32 = 0;
+        let mut best_match: Option<(usize, i32)> = None;
+        let mut best_cost: i32 = 1000;
+
+        for (index, &other) in possibilities.iter().enumerate() {
+            let distance: i32 = self.match_distance(desired, other);
+            let cost: i32 = distance + rank_cost;
+            if distance == 0 {
+                return Some((index, 0));
+            }
+            if distance < cutoff && cost < best_cost {
+                best_match = Some((index, distance));
+                best_cost = cost;
+            }
+            rank_cost += rank_penalty;
+            if rank_cost >= best_cost {
+                break;
+            }
+        }
+        best_match
+    }
+
+    /// Find the best match for a desired language among the supported
+    /// languages, as in `LanguageCode::match_supported_with_cutoff`, using
+    /// these options.
+    pub fn match_supported_with_cutoff(&self,
+                                       desired: LanguageCode,
+                                       cutoff: i32,
+                                       supported: &[LanguageCode])
+                                       -> (LanguageCode, i32) {
+        for &other in supported {
+            if other == desired {
+                return (other, 0);
+            }
+        }
+        self.find_match(desired, 0, cutoff, supported)
+    }
+}
+
+
+/// Check whether two scripts are Simplified and Traditional Chinese
+/// characters, in either order.
+fn is_chinese_script_pair(script1: u64, script2: u64) -> bool {
+    (script1 == SIMPLIFIED && script2 == TRADITIONAL) ||
+    (script1 == TRADITIONAL && script2 == SIMPLIFIED)
+}
+
+/// Find the best match between a list of desired languages, in order of
+/// preference, and a list of supported languages. Each desired language
+/// after the first adds `rank_penalty` to the cost of its matches, so an
+/// earlier desired language wins unless a later one matches better by more
+/// than the penalty. `rank_penalty` must not be negative.
+///
+/// Returns the desired language that was matched and its distance from the
+/// supported language it matched, or `(languages::UNKNOWN, 1000)` if nothing
+/// matches within the cutoff. Use `match_lists_supported_with_cutoff` to get
+/// the supported language instead.
+pub fn match_lists_with_cutoff(rank_penalty: i32,
+                               cutoff: i32,
+                               desired: &[LanguageCode],
+                               supported: &[LanguageCode])
+                               -> (LanguageCode, i32) {
+    match best_list_match(rank_penalty, cutoff, desired, supported) {
+        Some((desired_match, _, distance)) => (desired_match, distance),
+        None => (languages::UNKNOWN, 1000),
+    }
+}
+
+/// Find the best match between a list of desired languages and a list of
+/// supported languages, as in `match_lists_with_cutoff`, but return the
+/// supported language that was chosen and its distance, or
+/// `(languages::UNKNOWN, 1000)` if nothing matches within the cutoff.
+pub fn match_lists_supported_with_cutoff(rank_penalty: i32,
+                                         cutoff: i32,
+                                         desired: &[LanguageCode],
+                                         supported: &[LanguageCode])
+                                         -> (LanguageCode, i32) {
+    match best_list_match(rank_penalty, cutoff, desired, supported) {
+        Some((_, supported_match, distance)) => (supported_match, distance),
+        None => (languages::UNKNOWN, 1000),
+    }
+}
+
+/// Find the best (desired, supported, distance) match between two lists, for
+/// `match_lists_with_cutoff` and `match_lists_supported_with_cutoff`.
+fn best_list_match(rank_penalty: i32,
+                   cutoff: i32,
+                   desired: &[LanguageCode],
+                   supported: &[LanguageCode])
+                   -> Option<(LanguageCode, LanguageCode, i32)> {
+    let mut rank_cost: i32 = 0;
+    let mut best_match: Option<(LanguageCode, LanguageCode, i32)> = None;
+    let mut best_cost: i32 = 1000;
+    debug_assert!(rank_penalty >= 0, "rank_penalty must not be negative");
+    for &d in desired {
+        let (matched, distance) = d.match_supported_with_cutoff(cutoff, supported);
+        let cost: i32 = distance + rank_cost;
+        if distance < cutoff && cost < best_cost {
+            if distance == 0 {
+                // No later desired language can cost less than this one.
+                return Some((d, matched, 0));
+            }
+            best_match = Some((d, matched, distance));
+            best_cost = cost;
+        }
+        rank_cost += rank_penalty;
+        if rank_cost >= best_cost {
+            break;
+        }
+    }
+    best_match
+}
+
+/// Iterate over the pairs of codes whose match distance CLDR specifies
+/// explicitly, with their distances, as (desired, supported, distance).
+/// Pairs that aren't listed fall through to the general rules in
+/// `match_distance`. The codes are as they appear in the data, so they may
+/// not be normalized; `sh` appears as itself, for example.
+pub fn known_distances() -> impl Iterator<Item = (LanguageCode, LanguageCode, i32)> {
+    langdata::MATCH_DISTANCE.entries().map(|(pair, &distance)| {
+        let (desired, supported) = split_pair_bytes(pair);
+        (LanguageCode::new(desired), LanguageCode::new(supported), distance)
+    })
+}
+
+/// Iterate over the likely-subtags data that `maximize` uses, as pairs of a
+/// code and the maximal code that CLDR gives for it, such as `und` and
+/// `en-Latn-US`. Codes that aren't listed are maximized by broadening them
+/// until they match something that is. As in `known_distances`, the codes
+/// are as they appear in the data, so `zh-TW` appears as itself.
+pub fn likely_subtags() -> impl Iterator<Item = (LanguageCode, LanguageCode)> {
+    langdata::LIKELY_SUBTAGS
+        .entries()
+        .map(|(&from, &to)| (LanguageCode::new(from), LanguageCode::new(to)))
+}
+
+/// Check whether a language tag uses deprecated or legacy forms, meaning
+/// that `parse` would replace some of it. For example, "iw" and "sh" are
+/// deprecated (they're now "he" and "sr-Latn"), and so is "zh-CN", which
+/// implies the script in "zh-Hans-CN". Tags that don't parse at all are
+/// not considered deprecated.
+pub fn tag_is_deprecated(tag: &str) -> bool {
+    match (LanguageCode::parse_raw(tag), LanguageCode::parse(tag)) {
+        (Ok(raw), Ok(normal)) => raw != normal,
+        _ => false,
+    }
+}
+
+/// Check whether a language tag differs in any way from the standard form
+/// that `parse` would give it, such as in its case, its use of underscores,
+/// or deprecated or implied subtags. "en-US" is already normalized, while
+/// "EN_us", "iw", and "zh-CN" are not. As in `tag_is_deprecated`, tags
+/// that don't parse at all return false.
+pub fn needs_normalization(tag: &str) -> bool {
+    match LanguageCode::parse(tag) {
+        Ok(code) => code.to_string() != tag,
+        Err(_) => false,
+    }
+}
+
+/// List the subtags of a language tag that `parse` changes, as pairs of the
+/// original subtag and its replacement. A subtag that `parse` adds, such as
+/// the script that "zh-CN" implies, is paired with an empty original, and a
+/// subtag that a LanguageCode can't store, such as a variant, is paired with
+/// an empty replacement. "EN_us" gives `[("EN", "en"), ("us", "US")]`, and
+/// "iw" gives `[("iw", "he")]`. Tags that don't parse give an empty list,
+/// as in `needs_normalization`.
+pub fn normalization_diff(tag: &str) -> Vec<(String, String)> {
+    let code = match LanguageCode::parse(tag) {
+        Ok(code) => code,
+        Err(_) => return Vec::new(),
+    };
+    let canonical = |role: &SubtagRole| -> Option<String> {
+        match *role {
+            SubtagRole::Language => Some(code.language_subtag()),
+            SubtagRole::Extlang => decode_extlang(code.data),
+            SubtagRole::Script => code.get_script(),
+            SubtagRole::Region => code.get_region(),
+            _ => None,
+        }
+    };
+    let mut diff: Vec<(String, String)> = Vec::new();
+    let mut seen: Vec<SubtagRole> = Vec::new();
+    for (text, role) in classify_subtags(tag) {
+        let replacement: String = canonical(&role).unwrap_or_default();
+        if replacement != text {
+            diff.push((text, replacement));
+        }
+        seen.push(role);
+    }
+    for role in &[SubtagRole::Language, SubtagRole::Extlang, SubtagRole::Script, SubtagRole::Region] {
+        if !seen.contains(role) {
+            if let Some(added) = canonical(role) {
+                diff.push((String::new(), added));
+            }
+        }
+    }
+    diff
+}
+
+/// Check whether two language tags mean the same thing once they're parsed
+/// and normalized, such as "zh-CN" and "zh-Hans-CN". If either tag fails to
+/// parse, they are not equivalent.
+pub fn tags_equivalent(tag1: &str, tag2: &str) -> bool {
+    match (LanguageCode::parse(tag1), LanguageCode::parse(tag2)) {
+        (Ok(code1), Ok(code2)) => code1 == code2,
+        _ => false,
+    }
+}
+
+/// Check whether the scripts of two codes can appear together in one
+/// writing system, such as `Hira` and `Kana` in Japanese, or `Hang` and
+/// `Hani` in Korean. A script is compatible with itself, and the common
+/// (`Zyyy`) and inherited (`Zinh`) scripts, which are used for punctuation
+/// and marks, are compatible with every script. Codes without a script
+/// aren't compatible with anything; maximize them first to compare their
+/// likely scripts.
+///
+/// The groups are in `data/script_groups.txt`. They follow the combinations
+/// that ISO 15924 gives their own codes: `Jpan` (Han with Hiragana and
+/// Katakana), `Kore` (Han with Hangul), and `Hanb` (Han with Bopomofo).
+/// Simplified (`Hans`) and Traditional (`Hant`) Han are each compatible with
+/// `Hani`, but not with each other, just as the matcher keeps them apart.
+pub fn scripts_compatible(a: LanguageCode, b: LanguageCode) -> bool {
+    let script_a: u64 = a.data & SCRIPT_MASK;
+    let script_b: u64 = b.data & SCRIPT_MASK;
+    if script_a == 0 || script_b == 0 {
+        return false;
+    }
+    let universal = |script: u64| {
+        script == languages::COMMON_SCRIPT.data || script == languages::INHERIT_SCRIPT.data
+    };
+    script_a == script_b || universal(script_a) || universal(script_b) ||
+    langdata::SCRIPT_GROUPS.iter().any(|group| group.contains(&script_a) && group.contains(&script_b))
+}
+
+/// Get the most likely language for a script, such as `ko` for `und-Hang`,
+/// according to the likely-subtags data. Only the script of `script_code`
+/// is used. Returns None if it has no script, or if the data doesn't give a
+/// language for it. That includes `Latn`, which is used for so many
+/// languages that CLDR doesn't pick one; `maximize()` would fall back on the
+/// root locale and give `en` instead.
+pub fn language_for_script(script_code: LanguageCode) -> Option<LanguageCode> {
+    let script: u64 = script_code.data & SCRIPT_MASK;
+    if script == 0 {
+        return None;
+    }
+    langdata::LIKELY_SUBTAGS.get(&script).map(|&max| LanguageCode::new(max & LANGUAGE_EXT_MASK))
+}
+
+/// Maximize each of a slice of codes, as in `LanguageCode::maximize`,
+/// reusing the same scratch space for all of them.
+pub fn maximize_all(codes: &[LanguageCode]) -> Vec<LanguageCode> {
+    let mut buffer: Vec<LanguageCode> = Vec::with_capacity(7);
+    codes.iter().map(|code| code.maximize_with(ROOT_MAXIMIZED, &mut buffer)).collect()
+}
+
+/// Parse each of a sequence of language tags, as in `LanguageCode::parse`,
+/// yielding a `Result` for each one.
+pub fn parse_all_results<I, S>(tags: I) -> impl Iterator<Item = Result<LanguageCode, LanguageCodeError>>
+    where I: IntoIterator<Item = S>,
+          S: AsRef<str>
+{
+    tags.into_iter().map(|tag| LanguageCode::parse(tag.as_ref()))
+}
+
+/// Parse each of a sequence of language tags, as in `LanguageCode::parse`,
+/// skipping the ones that fail to parse.
+pub fn parse_all<I, S>(tags: I) -> impl Iterator<Item = LanguageCode>
+    where I: IntoIterator<Item = S>,
+          S: AsRef<str>
+{
+    parse_all_results(tags).filter_map(|result| result.ok())
+}
+
+/// Parse a list of language tags separated by whitespace, as some CLDR
+/// fields write them, such as "en fr de-CH".
+pub fn parse_space_list(s: &str) -> Vec<Result<LanguageCode, LanguageCodeError>> {
+    parse_all_results(s.split_ascii_whitespace()).collect()
+}
+
+/// What `normalize_column` does with a tag that fails to parse.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum InvalidTagPolicy {
+    /// Keep the tag as it was
+    KeepOriginal,
+    /// Leave the tag out of the results
+    Drop,
+    /// Replace the tag with "und"
+    ReplaceWithUnd,
+}
+
+/// Normalize a column of language tags, replacing each tag that parses with
+/// its standard form, such as "zh-Hant-TW" for "zh_TW". Tags that don't parse
+/// are handled according to `policy`.
+pub fn normalize_column(tags: &[&str], policy: InvalidTagPolicy) -> Vec<String> {
+    tags.iter()
+        .filter_map(|&tag| {
+            match (LanguageCode::parse(tag), policy) {
+                (Ok(code), _) => Some(code.to_string()),
+                (Err(_), InvalidTagPolicy::KeepOriginal) => Some(tag.to_string()),
+                (Err(_), InvalidTagPolicy::Drop) => None,
+                (Err(_), InvalidTagPolicy::ReplaceWithUnd) => Some(languages::UNKNOWN.to_string()),
+            }
+        })
+        .collect()
+}
+
+/// Parse a file or stream with one language tag per line, yielding the
+/// line number (starting at 1) and the parse result for each tag. Blank lines
+/// and lines starting with `#` are skipped, and whitespace around each tag is
+/// ignored. A line that can't be read, such as one that isn't valid UTF-8,
+/// gives a `ParseError` describing the I/O error. Invalid UTF-8 only affects
+/// its own line, but any other I/O error ends the results, as the rest of
+/// the input can't be read.
+pub fn parse_lines<R: BufRead>(reader: R)
+    -> impl Iterator<Item = (usize, Result<LanguageCode, LanguageCodeError>)>
+{
+    reader.lines()
+        .enumerate()
+        .scan(false, |stopped, (index, line)| {
+            if *stopped {
+                return None;
+            }
+            match line {
+                Ok(line) => {
+                    let tag = line.trim();
+                    if tag.is_empty() || tag.starts_with('#') {
+                        Some(None)
+                    } else {
+                        Some(Some((index + 1, LanguageCode::parse(tag))))
+                    }
+                }
+                Err(err) => {
+                    *stopped = err.kind() != io::ErrorKind::InvalidData;
+                    Some(Some((index + 1, Err(LanguageCodeError::ParseError(err.to_string())))))
+                }
+            }
+        })
+        .flatten()
+}
+
+/// A description of one subtag of a language tag, as produced by `analyze`.
+#[derive(PartialEq, Debug, Clone)]
+pub struct SubtagInfo {
+    /// The subtag as it appeared in the input
+    pub text: String,
+    /// The role of the subtag, based on its shape and position
+    pub role: SubtagRole,
+    /// Whether the subtag appears in the CLDR data that this crate is built
+    /// from. This is only checked for languages, extlangs, scripts, and
+    /// regions; other kinds of subtags are never marked as registered.
+    pub registered: bool,
+}
+
+/// Break down a language tag into its subtags, describing the role of each
+/// one and whether it's a known value. This never fails: subtags that
+/// wouldn't parse are given the role `SubtagRole::Unknown`. It's meant for
+/// showing users what's going on in a tag they're typing.
+pub fn analyze(tag: &str) -> Vec<SubtagInfo> {
+    classify_subtags(tag)
+        .into_iter()
+        .map(|(text, role)| {
+            let registered: bool = match role {
+                SubtagRole::Language | SubtagRole::Extlang => {
+                    match encode_language(&text) {
+                        Ok(EMPTY_CODE) => true,
+                        Ok(val) => {
+                            langdata::LANG_REPLACE.contains_key(&val) ||
+                            subtag_in_likely_subtags(LANGUAGE_MASK, val)
+                        }
+                        Err(_) => text.to_lowercase() == "root",
+                    }
+                }
+                SubtagRole::Script => {
+                    match encode_script(&text) {
+                        Ok(val) => {
+                            val == INHERIT_SCRIPT || val == INHERIT_SCRIPT_OLD ||
+                            subtag_in_likely_subtags(SCRIPT_MASK, val)
+                        }
+                        Err(_) => false,
+                    }
+                }
+                SubtagRole::Region => {
+                    match encode_region(&text) {
+                        Ok(val) => {
+                            langdata::REGION_REPLACE.contains_key(&val) ||
+                            subtag_in_likely_subtags(REGION_MASK, val)
+                        }
+                        Err(_) => false,
+                    }
+                }
+                _ => false,
+            };
+            SubtagInfo {
+                text,
+                role,
+                registered,
+            }
+        })
+        .collect()
+}
+
+/// Check whether an encoded subtag appears in the likely-subtags data, on
+/// either side of a mapping.
+fn subtag_in_likely_subtags(mask: u64, val: u64) -> bool {
+    langdata::LIKELY_SUBTAGS
+        .entries()
+        .any(|(&from, &to)| from & mask == val || to & mask == val)
+}
+
+/// Get the distance from every desired language to every supported language,
+/// as a matrix with one row per desired language and one column per
+/// supported language. Each language is maximized only once, instead of
+/// once per pair as calling `match_distance` on every pair would do.
+pub fn match_matrix(desired: &[LanguageCode], supported: &[LanguageCode]) -> Vec<Vec<i32>> {
+    let config = MatcherConfig::default();
+    let max_supported: Vec<LanguageCode> = supported.iter().map(|code| code.maximize()).collect();
+    desired.iter()
+        .map(|code| {
+            let max_desired = code.maximize();
+            max_supported.iter()
+                .map(|&other| config.match_distance_maximized(max_desired, other))
+                .collect()
+        })
+        .collect()
+}
+
+/// A rough description of how close two languages are, based on their
+/// match distance.
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum DistanceClass {
+    /// The same language, or a minor variation of it (up to
+    /// `MATCH_DISTANCE_MINOR`)
+    Minor,
+    /// Different, but probably comprehensible (up to
+    /// `MATCH_DISTANCE_COMPREHENSIBLE`)
+    Comprehensible,
+    /// Related in some way, but not likely to be understood
+    Distant,
+    /// Unrelated languages (`MATCH_DISTANCE_UNRELATED` or more)
+    Unrelated,
+}
+
+/// Describe a match distance using the thresholds that are meaningful in
+/// CLDR's language matching data.
+pub fn classify_distance(distance: i32) -> DistanceClass {
+    if distance <= MATCH_DISTANCE_MINOR {
+        DistanceClass::Minor
+    } else if distance <= MATCH_DISTANCE_COMPREHENSIBLE {
+        DistanceClass::Comprehensible
+    } else if distance < MATCH_DISTANCE_UNRELATED {
+        DistanceClass::Distant
+    } else {
+        DistanceClass::Unrelated
+    }
+}
+
+/// How good a match is, for showing to users. This is like `DistanceClass`,
+/// but it sets exact matches apart from minor variations.
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum MatchQuality {
+    /// A distance of 0: the languages match exactly
+    Exact,
+    /// A minor variation (up to `MATCH_DISTANCE_MINOR`)
+    Excellent,
+    /// Probably comprehensible (up to `MATCH_DISTANCE_COMPREHENSIBLE`)
+    Good,
+    /// Related, but not likely to be understood (less than
+    /// `MATCH_DISTANCE_UNRELATED`)
+    Poor,
+    /// Unrelated languages, which shouldn't be used as a match
+    Unusable,
+}
+
+/// Describe how good a match is, given its match distance.
+pub fn match_quality(distance: i32) -> MatchQuality {
+    if distance == 0 {
+        return MatchQuality::Exact;
+    }
+    match classify_distance(distance) {
+        DistanceClass::Minor => MatchQuality::Excellent,
+        DistanceClass::Comprehensible => MatchQuality::Good,
+        DistanceClass::Distant => MatchQuality::Poor,
+        DistanceClass::Unrelated => MatchQuality::Unusable,
+    }
+}
+
+/// Check whether exactly this code appears in a list of supported codes,
+/// without any language matching. For example, `en-US` is not contained in
+/// `[en]`.
+pub fn contains_exact(supported: &[LanguageCode], code: LanguageCode) -> bool {
+    supported.contains(&code)
+}
+
+/// A set of supported language codes that can check for exact membership in
+/// constant time, for when the list is too long to search through.
+#[derive(Clone, Debug, Default)]
+pub struct SupportedSet {
+    codes: HashSet<LanguageCode>,
+}
+
+impl SupportedSet {
+    pub fn new(supported: &[LanguageCode]) -> SupportedSet {
+        SupportedSet { codes: supported.iter().cloned().collect() }
+    }
+
+    pub fn insert(&mut self, code: LanguageCode) {
+        self.codes.insert(code);
+    }
+
+    /// Check whether exactly this code is in the set, without any language
+    /// matching.
+    pub fn contains_exact(&self, code: LanguageCode) -> bool {
+        self.codes.contains(&code)
+    }
+}
+
+/// Measure how much two sets of supported languages cover each other. Each
+/// language in either set counts as covered if it has a match in the other
+/// set with a distance less than `cutoff`, and the result is the fraction of
+/// all the languages, from both sets, that are covered.
+///
+/// This is 1.0 when every language on each side has a match on the other
+/// side, and 0.0 when no language does. For example, `[en, fr]` and
+/// `[en-GB, de]` have an overlap of 0.5, because `en` and `en-GB` are close
+/// matches but `fr` and `de` are unmatched. Two empty sets have an overlap
+/// of 1.0.
+pub fn coverage_overlap(a: &[LanguageCode], b: &[LanguageCode], cutoff: i32) -> f64 {
+    let total: usize = a.len() + b.len();
+    if total == 0 {
+        return 1.0;
+    }
+    let covered_a: usize = a.iter()
+        .filter(|code| code.match_supported_with_cutoff(cutoff, b).1 < cutoff)
+        .count();
+    let covered_b: usize = b.iter()
+        .filter(|code| code.match_supported_with_cutoff(cutoff, a).1 < cutoff)
+        .count();
+    (covered_a + covered_b) as f64 / total as f64
+}
+
+
+/// Get the total weight of the desired languages in `demand` that match a
+/// supported language with a distance less than `cutoff`. The weights could
+/// be numbers of users, for example. Comparing the scores of two sets of
+/// supported languages shows which one covers more of the demand.
+pub fn coverage_score(supported: &[LanguageCode],
+                      demand: &[(LanguageCode, u64)],
+                      cutoff: i32)
+                      -> u64 {
+    demand.iter()
+        .filter(|&&(desired, _)| desired.match_supported_with_cutoff(cutoff, supported).1 < cutoff)
+        .map(|&(_, weight)| weight)
+        .sum()
+}
+
+/// Choose the one candidate language that would most improve coverage if it
+/// were added to the supported languages. `demand` lists desired languages
+/// with their weights, such as numbers of users. Each candidate is scored
+/// by the total weight of the desired languages it would match, within
+/// `MATCH_DISTANCE_COMPREHENSIBLE`, that the supported languages don't
+/// already match.
+///
+/// Returns the first candidate with the highest score, or None if no
+/// candidate would cover any new demand.
+pub fn best_addition(supported: &[LanguageCode],
+                     demand: &[(LanguageCode, u64)],
+                     candidates: &[LanguageCode])
+                     -> Option<LanguageCode> {
+    let cutoff: i32 = MATCH_DISTANCE_COMPREHENSIBLE;
+    let unmet: Vec<(LanguageCode, u64)> = demand.iter()
+        .cloned()
+        .filter(|&(desired, _)| desired.match_supported_with_cutoff(cutoff, supported).1 >= cutoff)
+        .collect();
+    let mut best: Option<LanguageCode> = None;
+    let mut best_gain: u64 = 0;
+    for &candidate in candidates {
+        let gain: u64 = unmet.iter()
+            .filter(|&&(desired, _)| desired.match_distance(candidate) < cutoff)
+            .map(|&(_, weight)| weight)
+            .sum();
+        if gain > best_gain {
+            best = Some(candidate);
+            best_gain = gain;
+        }
+    }
+    best
+}
+
+
+impl FromStr for LanguageCode {
+    type Err = LanguageCodeError;
+
+    /// Parse a LanguageCode from its string representation. The result
+    /// is a constant-sized Struct that encodes its language, script, and
+    /// region.
+    fn from_str(s: &str) -> Result<LanguageCode, LanguageCodeError> {
+        LanguageCode::parse(&s)
+    }
+}
+
+
+/// Serialize a LanguageCode as its underlying 64-bit integer, which takes 8
+/// bytes in a binary format such as bincode, instead of as a string. Use it
+/// on a field with `#[serde(with = "language_codes::as_u64")]`.
+///
+/// When deserializing, the integer must be one that `parse` could have
+/// produced; otherwise, this returns an error.
+#[cfg(feature = "serde-compact")]
+pub mod as_u64 {
+    use serde::{Serialize, Serializer, Deserialize, Deserializer};
+    use serde::de::Error;
+    use super::{LanguageCode, encode_tag, decode_tag};
+
+    pub fn serialize<S>(code: &LanguageCode, serializer: S) -> Result<S::Ok, S::Error>
+        where S: Serializer
+    {
+        code.data.serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<LanguageCode, D::Error>
+        where D: Deserializer<'de>
+    {
+        let val: u64 = u64::deserialize(deserializer)?;
+        if encode_tag(&decode_tag(val)) == Ok(val) {
+            Ok(LanguageCode::new(val))
+        } else {
+            Err(D::Error::custom(format!("0x{:016x} is not a valid language code", val)))
+        }
+    }
+}
+
+
+/// Options that loosen what `LanguageCode::parse` accepts. The default
+/// options accept exactly what `parse` does.
+#[derive(Clone, Debug, Default)]
+pub struct ParseOptions {
+    /// Ignore a POSIX-style `.charset` suffix, such as the ".UTF-8" in
+    /// "en_US.UTF-8", by removing everything from the first `.` onward
+    /// before parsing.
+    pub posix_tolerant: bool,
+}
+
+impl ParseOptions {
+    pub fn new() -> ParseOptions {
+        ParseOptions::default()
+    }
+
+    /// Parse a language tag, as in `LanguageCode::parse`, using these
+    /// options.
+    pub fn parse(&self, tag: &str) -> Result<LanguageCode, LanguageCodeError> {
+        let tag: &str = match tag.find('.') {
+            Some(index) if self.posix_tolerant => &tag[..index],
+            _ => tag,
+        };
+        LanguageCode::parse(tag)
+    }
+}
+
+/// A cache of parsed language tags, for when the same tags need to be parsed
+/// over and over. Each distinct tag string is only parsed once. Tags that
+/// fail to parse aren't cached, so they're parsed again each time.
+#[derive(Clone, Debug, Default)]
+pub struct TagInterner {
+    cache: HashMap<String, LanguageCode>,
+}
+
+impl TagInterner {
+    pub fn new() -> TagInterner {
+        TagInterner::default()
+    }
+
+    /// Parse a language tag, as in `LanguageCode::parse`, returning the
+    /// cached result if this tag has been seen before.
+    pub fn parse(&mut self, tag: &str) -> Result<LanguageCode, LanguageCodeError> {
+        if let Some(&code) = self.cache.get(tag) {
+            return Ok(code);
+        }
+        let code: LanguageCode = LanguageCode::parse(tag)?;
+        self.cache.insert(tag.to_string(), code);
+        Ok(code)
+    }
+}
 
 
 /// A convenient function for declaring language codes from literals.
@@ -448,58 +2103,632 @@ pub fn lang(s: &str) -> LanguageCode {
     LanguageCode::parse(&s).unwrap()
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let code: LanguageCode = "zh-hant-tw".parse().unwrap();
+        assert_eq!(code.get_language(), Some("zh".to_string()));
+        assert_eq!(code.get_script(), Some("Hant".to_string()));
+        assert_eq!(code.get_region(), Some("TW".to_string()));
+        assert_eq!(code.to_string(), "zh-Hant-TW");
+    }
+
+    fn parses_as(input: &str, result: &str) {
+        let code: LanguageCode = input.parse().unwrap();
+        assert_eq!(code.to_string(), result);
+    }
+
+    fn maximizes_to(input: &str, result: &str) {
+        let code: LanguageCode = input.parse().unwrap();
+        assert_eq!(code.maximize().to_string(), result);
+    }
+
+    fn minimizes_to(input: &str, result: &str) {
+        let code: LanguageCode = input.parse().unwrap();
+        assert_eq!(code.minimize().to_string(), result);
+    }
+
+    fn check_distance(lang1: &str, lang2: &str, dist: i32) {
+        let code1: LanguageCode = lang1.parse().unwrap();
+        let code2: LanguageCode = lang2.parse().unwrap();
+        assert_eq!(code1.match_distance(code2), dist)
+    }
+
+    #[test]
+    fn test_replacement() {
+        parses_as("sh-ME", "sr-Latn-ME");
+        parses_as("sh-Cyrl", "sr-Cyrl");
+        parses_as("sgn-be-fr", "sfb");
+        parses_as("no-bokmal", "nb");
+        parses_as("mn-Cyrl-MN", "mn-MN");
+        parses_as("zh-CN", "zh-Hans-CN");
+        parses_as("i-hak", "hak");
+        parses_as("en-UK", "en-GB");
+        parses_as("fra", "fr");
+        parses_as("fre", "fr");
+        parses_as("fi-zZZZ-zZ", "fi");
+        parses_as("es-419", "es-419");
+        parses_as("en-840", "en-US");
+        parses_as("de-DD", "de-DE");
+        parses_as("sh-QU", "sr-Latn-EU");
+        parses_as("sh-Qaai", "sr-Zinh");
+    }
+
+    #[test]
+    fn test_is_adequately_supported() {
+        let supported = [lang("en-US"), lang("fr"), lang("zh-Hans")];
+        assert!(lang("en-GB").is_adequately_supported(&supported, MATCH_DISTANCE_COMPREHENSIBLE));
+        // zh-Hant is 23 from zh-Hans, and mg is 14 from fr
+        assert!(lang("zh-Hant").is_adequately_supported(&supported, MATCH_DISTANCE_COMPREHENSIBLE));
+        assert!(!lang("zh-Hant").is_adequately_supported(&supported, 23));
+        assert!(lang("mg").is_adequately_supported(&supported, 15));
+        assert!(!lang("mg").is_adequately_supported(&supported, 14));
+        assert!(!lang("ja").is_adequately_supported(&supported, MATCH_DISTANCE_COMPREHENSIBLE));
+        assert!(!lang("en").is_adequately_supported(&[], MATCH_DISTANCE_COMPREHENSIBLE));
+    }
+
+    #[test]
+    fn test_match_supported_ref() {
+        let supported = [lang("en"), lang("pt-PT"), lang("pt-BR"), lang("pt-PT")];
+        let (matched, distance) = lang("pt-AO").match_supported_ref(&supported).unwrap();
+        assert_eq!((*matched, distance), (lang("pt-PT"), 4));
+        assert!(std::ptr::eq(matched, &supported[1]));
+
+        let (matched, distance) = lang("pt").match_supported_ref(&supported).unwrap();
+        assert_eq!(distance, 0);
+        assert!(std::ptr::eq(matched, &supported[2]));
+
+        assert_eq!(lang("ja").match_supported_ref(&supported), None);
+    }
+
+    #[test]
+    fn test_match_supported_with_language_fallback() {
+        // CLDR's rules for Spanish pick es-419 in the first stage.
+        let spanish = [lang("es-ES"), lang("es-419")];
+        let es_ar = lang("es-AR");
+        assert_eq!(es_ar.match_distance(lang("es-419")), 4);
+        assert_eq!(es_ar.match_distance(lang("es-ES")), 8);
+        assert_eq!(es_ar.match_supported_with_language_fallback(&spanish, 25),
+                   Some((lang("es-419"), 4)));
+        assert_eq!(es_ar.match_supported_with_language_fallback(&spanish, 6),
+                   Some((lang("es-419"), 4)));
+
+        // Below that cutoff, the fallback still prefers es-419 by its real
+        // distance, which it reports even though it's beyond the cutoff.
+        assert_eq!(es_ar.match_supported_with_language_fallback(&spanish, 3),
+                   Some((lang("es-419"), 4)));
+
+        // The fallback finds every region of German equally good, so de-AT
+        // and de-CH tie, and the first one listed wins.
+        let german = [lang("fr"), lang("de-AT"), lang("de-CH")];
+        let de_lu = lang("de-LU");
+        assert_eq!(de_lu.match_supported_with_language_fallback(&german, 25),
+                   Some((lang("de-AT"), 4)));
+        assert_eq!(de_lu.match_supported_with_cutoff(3, &german).0, lang("und"));
+        assert_eq!(de_lu.match_supported_with_language_fallback(&german, 3),
+                   Some((lang("de-AT"), 4)));
+        assert_eq!(de_lu.match_distance(lang("de-CH")), 4);
+
+        assert_eq!(lang("ja").match_supported_with_language_fallback(&german, 25), None);
+    }
+
+    #[test]
+    fn test_display() {
+        for &tag in ["en", "zh-Hant-TW", "sr-Latn", "es-419", "und", "und-Zinh", "gem-pro"].iter() {
+            let code = lang(tag);
+            assert_eq!(format!("{}", code), code.to_string());
+            assert_eq!(code.to_string(), decode_tag(code.data));
+        }
+        assert_eq!(format!("[{}]", lang("zh-TW")), "[zh-Hant-TW]");
+    }
+
+    #[test]
+    fn test_script_fallback() {
+        assert_eq!(lang("sr-Latn").script_fallback(), Some(lang("und-Latn")));
+        assert_eq!(lang("ru").script_fallback(), Some(lang("und-Cyrl")));
+        assert_eq!(lang("und-RU").script_fallback(), Some(lang("und-Cyrl")));
+
+        // "qaa" is reserved for private use, so there's no data about it
+        assert_eq!(lang("qaa").script_fallback(), Some(languages::COMMON_SCRIPT));
+        assert_eq!(lang("qaa").maximize(), lang("qaa-Latn-US"));
+        assert_eq!(lang("zxx").script_fallback(), Some(languages::UNWRITTEN_SCRIPT));
+        assert_eq!(lang("und").script_fallback(), None);
+    }
+
+    #[test]
+    fn test_multiple() {
+        assert!(languages::MULTIPLE_LANGUAGES.is_multiple());
+        assert!(!lang("en").is_multiple());
+        assert_eq!(lang("mul").match_distance(lang("en")), MATCH_DISTANCE_MULTIPLE);
+        assert_eq!(lang("en").match_distance(lang("mul")), MATCH_DISTANCE_MULTIPLE);
+        assert_eq!(lang("mul").match_distance(lang("mul")), 0);
+        assert_eq!(lang("mul").match_distance(lang("zxx")), MATCH_DISTANCE_UNRELATED);
+
+        let supported = [lang("ja"), lang("mul")];
+        assert_eq!(lang("fr").match_supported(&supported).0, languages::UNKNOWN);
+        assert_eq!(lang("fr").match_supported_with_cutoff(110, &supported),
+                   (lang("mul"), MATCH_DISTANCE_MULTIPLE));
+    }
+
+    #[test]
+    fn test_specific_language() {
+        assert!(lang("en").is_specific_language());
+        assert!(lang("zh-Hant-TW").is_specific_language());
+        assert!(lang("art").is_specific_language());
+        assert!(lang("qu").is_specific_language());
+        assert!(lang("qwe").is_specific_language());
+        assert!(!lang("und").is_specific_language());
+        assert!(!lang("und-Latn-US").is_specific_language());
+        assert!(!lang("mul").is_specific_language());
+        assert!(!lang("zxx").is_specific_language());
+        assert!(!lang("mis").is_specific_language());
+        assert!(!lang("qaa").is_specific_language());
+        assert!(!lang("qtz-US").is_specific_language());
+        assert!(!lang("x-klingon").is_specific_language());
+    }
+
+    #[test]
+    fn test_constructed() {
+        assert!(lang("art").is_constructed());
+        assert!(lang("art-x-tolkien").is_constructed());
+        assert_eq!(lang("art-x-tolkien"), lang("art"));
+        assert!(!lang("eo").is_constructed());
+        assert!(!lang("und").is_constructed());
+    }
+
+    #[test]
+    fn test_parse_all() {
+        let tags = vec!["en-US", "not a tag", "zh-CN", "en-$", "fr"];
+        let codes: Vec<LanguageCode> = parse_all(&tags).collect();
+        assert_eq!(codes, vec![lang("en-US"), lang("zh-Hans-CN"), lang("fr")]);
+
+        let owned: Vec<String> = tags.iter().map(|tag| tag.to_string()).collect();
+        let results: Vec<Result<LanguageCode, LanguageCodeError>> =
+            parse_all_results(owned).collect();
+        assert_eq!(results.len(), 5);
+        assert_eq!(results[0], Ok(lang("en-US")));
+        assert!(results[1].is_err());
+        assert_eq!(results[4], Ok(lang("fr")));
+    }
+
+    #[test]
+    fn test_parse_space_list() {
+        assert_eq!(parse_space_list("en fr  de-CH"),
+                   vec![Ok(lang("en")), Ok(lang("fr")), Ok(lang("de-CH"))]);
+        assert_eq!(parse_space_list("\tzh_TW\n"), vec![Ok(lang("zh-Hant-TW"))]);
+        assert_eq!(parse_space_list("  "), vec![]);
+        let results = parse_space_list("en en-$");
+        assert_eq!(results.len(), 2);
+        assert!(results[1].is_err());
+    }
+
+    #[test]
+    fn test_normalize_column() {
+        let column = ["en_us", "iw", "???", "zh-TW", "en-$", "english"];
+        assert_eq!(normalize_column(&column, InvalidTagPolicy::KeepOriginal),
+                   vec!["en-US", "he", "???", "zh-Hant-TW", "en-$", "english"]);
+        assert_eq!(normalize_column(&column, InvalidTagPolicy::Drop),
+                   vec!["en-US", "he", "zh-Hant-TW"]);
+        assert_eq!(normalize_column(&column, InvalidTagPolicy::ReplaceWithUnd),
+                   vec!["en-US", "he", "und", "zh-Hant-TW", "und", "und"]);
+    }
+
+    #[test]
+    fn test_parse_lines() {
+        let text = "# Languages of the corpus\nen-US\n\n  zh_TW  \nnot a tag\n#fr\nfr\n";
+        let results: Vec<(usize, Result<LanguageCode, LanguageCodeError>)> =
+            parse_lines(text.as_bytes()).collect();
+        assert_eq!(results.len(), 4);
+        assert_eq!(results[0], (2, Ok(lang("en-US"))));
+        assert_eq!(results[1], (4, Ok(lang("zh-Hant-TW"))));
+        assert_eq!(results[2].0, 5);
+        assert!(results[2].1.is_err());
+        assert_eq!(results[3], (7, Ok(lang("fr"))));
+
+        // A line that isn't UTF-8 is an error, and doesn't stop the rest.
+        let bytes: &[u8] = b"en\n\xff\xfe\n\nfr\n";
+        let results: Vec<(usize, Result<LanguageCode, LanguageCodeError>)> =
+            parse_lines(bytes).collect();
+        assert_eq!(results.len(), 3);
+        assert_eq!(results[0], (1, Ok(lang("en"))));
+        assert_eq!(results[1].0, 2);
+        assert!(results[1].1.is_err());
+        assert_eq!(results[2], (4, Ok(lang("fr"))));
+    }
+
+    #[test]
+    fn test_no_content() {
+        assert!(languages::NO_LINGUISTIC_CONTENT.is_no_content());
+        assert!(lang("zxx-US").is_no_content());
+        assert!(!lang("en").is_no_content());
+        assert!(!lang("und").is_no_content());
+        assert_eq!(lang("zxx").match_distance(lang("en")), MATCH_DISTANCE_UNRELATED);
+        assert_eq!(lang("en").match_distance(lang("zxx")), MATCH_DISTANCE_UNRELATED);
+        assert_eq!(lang("und").match_distance(lang("zxx")), MATCH_DISTANCE_UNRELATED);
+        assert_eq!(lang("zxx").match_distance(lang("zxx")), 0);
+
+        let supported = [lang("en"), lang("zxx")];
+        assert_eq!(lang("zxx").match_supported(&supported), (lang("zxx"), 0));
+        assert_eq!(lang("fr").match_supported(&supported).0, lang("und"));
+    }
+
+    #[test]
+    fn test_two_letter_fast_path() {
+        for first in b'a'..=b'z' {
+            for second in b'a'..=b'z' {
+                let tag = String::from_utf8(vec![first, second]).unwrap();
+                assert_eq!(LanguageCode::parse(&tag), LanguageCode::parse_general(&tag));
+            }
+        }
+        parses_as("iw", "he");
+        parses_as("in", "id");
+        parses_as("sh", "sr-Latn");
+        parses_as("no", "nb");
+        parses_as("en", "en");
+    }
+
+    #[test]
+    fn test_region_successors() {
+        let successors = lang("und-YU").region_successors();
+        assert!(successors.contains(&lang("und-RS")));
+        assert!(successors.contains(&lang("und-ME")));
+        assert_eq!(lang("hu-YU").region_successors(), vec![lang("hu-RS"), lang("hu-ME")]);
+        assert_eq!(lang("und-890").region_successors().len(), 6);
+        assert_eq!(lang("und-SU").region_successors()[0], lang("und-RU"));
+        assert_eq!(LanguageCode::parse_raw("de-DD").unwrap().region_successors(),
+                   vec![lang("de-DE")]);
+        assert_eq!(lang("hu-RS").region_successors(), vec![]);
+        assert_eq!(lang("sr").region_successors(), vec![]);
+    }
+
+    #[test]
+    fn test_numeric_region() {
+        parses_as("fr-250", "fr-FR");
+        parses_as("de-276", "de-DE");
+        parses_as("en-826", "en-GB");
+        parses_as("ja-392", "ja-JP");
+        parses_as("es-419", "es-419");
+        parses_as("en-001", "en-001");
+        parses_as("en-150", "en-150");
+    }
+
+    #[test]
+    fn test_subtag_types() {
+        let language: Language = "PT".parse().unwrap();
+        let script: Script = "cyrl".parse().unwrap();
+        let region: Region = "419".parse().unwrap();
+        assert_eq!(language.to_string(), "pt");
+        assert_eq!(script.to_string(), "Cyrl");
+        assert_eq!(region.to_string(), "419");
+        assert_eq!("und".parse::<Language>().unwrap().to_string(), "und");
+        assert_eq!("zzzz".parse::<Script>().unwrap().to_string(), "Zzzz");
+        assert_eq!("zz".parse::<Region>().unwrap().to_string(), "ZZ");
+
+        // Subtags of the wrong shape are rejected
+        assert!("Latn".parse::<Language>().is_err());
+        assert!("BR".parse::<Script>().is_err());
+        assert!("pt".parse::<Script>().is_err());
+        assert!("Cyrl".parse::<Region>().is_err());
+        assert!("12".parse::<Region>().is_err());
+        assert!("p-t".parse::<Language>().is_err());
+
+        let code = lang("sr-Cyrl-ME");
+        assert_eq!(Language::from(code), "sr".parse().unwrap());
+        assert_eq!(Script::from(code), script);
+        assert_eq!(Region::from(code).to_string(), "ME");
+        assert_eq!(LanguageCode::from(language), lang("pt"));
+        assert_eq!(LanguageCode::from(script), lang("und-Cyrl"));
+        assert_eq!(LanguageCode::from(region), lang("und-419"));
+    }
+
+    #[test]
+    fn test_extlang_code() {
+        let proto = lang("ine-pro").extlang_code().unwrap();
+        assert_eq!(proto, lang("gem-pro").extlang_code().unwrap());
+        assert_eq!(proto.get_extlang(), Some("pro".to_string()));
+        assert_eq!(proto.get_language(), None);
+
+        let extlang = lang("roa-opt-pro").extlang_code().unwrap();
+        assert_eq!(extlang.get_extlang(), Some("opt-pro".to_string()));
+        assert!(extlang != proto);
+
+        assert_eq!(lang("en").extlang_code(), None);
+    }
+
+    #[test]
+    fn test_script_numeric() {
+        assert_eq!(lang("und-Latn").script_numeric(), Some(215));
+        assert_eq!(lang("zh-Hant").script_numeric(), Some(502));
+        assert_eq!(lang("sr-Cyrl-RS").script_numeric(), Some(220));
+        assert_eq!(lang("und-Zinh").script_numeric(), Some(994));
+        assert_eq!(lang("en").script_numeric(), None);
+        assert_eq!(lang("en").maximize().script_numeric(), Some(215));
+        assert_eq!(lang("und-Qaaa").script_numeric(), None);
+    }
+
+    #[test]
+    fn test_script_properties() {
+        let arabic = lang("und-Arab").script_properties().unwrap();
+        assert!(arabic.rtl);
+        assert!(arabic.requires_shaping);
+        assert!(!arabic.cased);
+        assert_eq!(lang("en-Latn").script_properties(),
+                   Some(ScriptProperties { rtl: false, cased: true, requires_shaping: false }));
+        assert_eq!(lang("he").maximize().script_properties().map(|props| props.rtl), Some(true));
+        assert_eq!(lang("en").script_properties(), None);
+        assert_eq!(lang("und-Zyyy").script_properties(), None);
+    }
+
+    #[test]
+    fn test_canonical() {
+        // NORWEGIAN is `no`, the way people usually write it, which parses
+        // as `nb`. Every other language constant is canonical.
+        for &code in languages::ALL.iter() {
+            assert_eq!(code.is_canonical(), code != languages::NORWEGIAN, "{}", code);
+        }
+        assert!(!LanguageCode::parse_raw("iw").unwrap().is_canonical());
+        assert!(!languages::INHERIT_SCRIPT_OLD.is_canonical());
+        assert!(lang("zh-CN").is_canonical());
+    }
+
+    #[test]
+    fn test_to_string_without_script() {
+        assert_eq!(lang("zh-Hant-TW").to_string_without_script(), "zh-TW");
+        assert_eq!(lang("en").maximize().to_string_without_script(), "en-US");
+        assert_eq!(lang("sr-Latn").to_string_without_script(), "sr");
+        assert_eq!(lang("und-Latn").to_string_without_script(), "und");
+    }
+
+    #[test]
+    fn test_slug() {
+        assert_eq!(lang("en-US").to_slug(), "en_us");
+        assert_eq!(lang("zh-TW").to_slug(), "zh_hant_tw");
+        assert_eq!(lang("es-419").to_slug(), "es_419");
+        assert_eq!(LanguageCode::from_slug("en_us"), Ok(lang("en-US")));
+        assert!(LanguageCode::from_slug("en_$").is_err());
+        for &code in languages::ALL.iter() {
+            let code = LanguageCode::parse(&code.to_string()).unwrap();
+            assert_eq!(LanguageCode::from_slug(&code.to_slug()), Ok(code));
+        }
+    }
+
+    #[test]
+    fn test_is_equivalent() {
+        let iw = LanguageCode::parse_raw("iw").unwrap();
+        assert!(iw != lang("he"));
+        assert!(iw.is_equivalent(lang("he")));
+        assert!(lang("he").is_equivalent(iw));
+
+        let sh = LanguageCode::parse_raw("sh").unwrap();
+        assert!(sh != lang("sr-Latn"));
+        assert!(sh.is_equivalent(lang("sr-Latn")));
+        assert!(!sh.is_equivalent(lang("sr-Cyrl")));
+        assert!(LanguageCode::parse_raw("zh-CN").unwrap().is_equivalent(lang("zh-Hans-CN")));
+
+        assert!(lang("en").is_equivalent(lang("en")));
+        assert!(!lang("en").is_equivalent(lang("en-US")));
+    }
+
+    #[test]
+    fn test_from_parts() {
+        assert_eq!(LanguageCode::from_parts("zh", Some("Hant"), Some("TW")),
+                   Ok(lang("zh-Hant-TW")));
+        assert_eq!(LanguageCode::from_parts("EN", None, Some("us")), Ok(lang("en-US")));
+        assert_eq!(LanguageCode::from_parts("iw", None, Some("IL")), Ok(lang("he-IL")));
+        assert_eq!(LanguageCode::from_parts("zh", None, Some("CN")), Ok(lang("zh-Hans-CN")));
+        assert_eq!(LanguageCode::from_parts("zh", Some("Hants"), None),
+                   Err(LanguageCodeError::SubtagFormatError("Hants".to_string())));
+    }
 
     #[test]
-    fn test_parse() {
-        let code: LanguageCode = "zh-hant-tw".parse().unwrap();
-        assert_eq!(code.get_language(), Some("zh"));
-        assert_eq!(code.get_script(), Some("Hant".to_string()));
-        assert_eq!(code.get_region(), Some("TW".to_string()));
-        assert_eq!(code.to_string(), "zh-Hant-TW");
+    fn test_parse_non_ascii() {
+        assert_eq!(LanguageCode::parse("frç"), Err(LanguageCodeError::NonAsciiInput('ç')));
+        assert_eq!(LanguageCode::parse("日本語"), Err(LanguageCodeError::NonAsciiInput('日')));
     }
 
-    fn parses_as(input: &str, result: &str) {
-        let code: LanguageCode = input.parse().unwrap();
-        assert_eq!(code.to_string(), result);
+    #[test]
+    fn test_parse_tolerant() {
+        assert!(LanguageCode::parse("en-US-Latn").is_err());
+        assert_eq!(LanguageCode::parse_tolerant("en-US-Latn"), Ok((lang("en-Latn-US"), true)));
+        assert_eq!(LanguageCode::parse_tolerant("sr_419_Cyrl"), Ok((lang("sr-Cyrl-419"), true)));
+        assert_eq!(LanguageCode::parse_tolerant("en-Latn-US"), Ok((lang("en-Latn-US"), false)));
+        assert_eq!(LanguageCode::parse_tolerant("en-US-Latn-Latn"),
+                   Err(LanguageCode::parse("en-US-Latn-Latn").unwrap_err()));
+        assert!(LanguageCode::parse_tolerant("en-US-fr").is_err());
     }
 
-    fn maximizes_to(input: &str, result: &str) {
-        let code: LanguageCode = input.parse().unwrap();
-        assert_eq!(code.maximize().to_string(), result);
+    #[test]
+    fn test_parse_with_remainder() {
+        assert_eq!(LanguageCode::parse_with_remainder("en-US-u-ca-gregory"),
+                   Ok((lang("en-US"), Some("u-ca-gregory".to_string()))));
+        assert_eq!(LanguageCode::parse_with_remainder("zh_TW_u_nu_hanidec_x_Foo"),
+                   Ok((lang("zh-Hant-TW"), Some("u_nu_hanidec_x_Foo".to_string()))));
+        assert_eq!(LanguageCode::parse_with_remainder("und-t-ja"),
+                   Ok((lang("und"), Some("t-ja".to_string()))));
+        assert_eq!(LanguageCode::parse_with_remainder("de-CH-1901"), Ok((lang("de-CH"), None)));
+        assert!(LanguageCode::parse_with_remainder("en-$-u-ca-gregory").is_err());
     }
 
-    fn minimizes_to(input: &str, result: &str) {
-        let code: LanguageCode = input.parse().unwrap();
-        assert_eq!(code.minimize().to_string(), result);
+    #[test]
+    fn test_parse_raw() {
+        assert_eq!(LanguageCode::parse_raw("iw").unwrap().to_string(), "iw");
+        assert_eq!(LanguageCode::parse_raw("en-UK").unwrap().to_string(), "en-UK");
+        assert_eq!(LanguageCode::parse_raw("zh-Hant-TW"), Ok(lang("zh-Hant-TW")));
     }
 
-    fn check_distance(lang1: &str, lang2: &str, dist: i32) {
-        let code1: LanguageCode = lang1.parse().unwrap();
-        let code2: LanguageCode = lang2.parse().unwrap();
-        assert_eq!(code1.match_distance(code2), dist)
+    #[test]
+    fn test_deprecated() {
+        assert!(tag_is_deprecated("iw"));
+        assert!(tag_is_deprecated("sh"));
+        assert!(tag_is_deprecated("zh-CN"));
+        assert!(tag_is_deprecated("en-UK"));
+        assert!(tag_is_deprecated("fra"));
+        assert!(!tag_is_deprecated("he"));
+        assert!(!tag_is_deprecated("en-US"));
+        assert!(!tag_is_deprecated("zh-Hans-CN"));
+        assert!(!tag_is_deprecated("not a tag"));
     }
 
     #[test]
-    fn test_replacement() {
-        parses_as("sh-ME", "sr-Latn-ME");
-        parses_as("sh-Cyrl", "sr-Cyrl");
-        parses_as("sgn-be-fr", "sfb");
-        parses_as("no-bokmal", "nb");
-        parses_as("mn-Cyrl-MN", "mn-MN");
-        parses_as("zh-CN", "zh-Hans-CN");
-        parses_as("i-hak", "hak");
-        parses_as("en-UK", "en-GB");
-        parses_as("fra", "fr");
-        parses_as("fre", "fr");
-        parses_as("fi-zZZZ-zZ", "fi");
-        parses_as("es-419", "es-419");
-        parses_as("en-840", "en-US");
-        parses_as("de-DD", "de-DE");
-        parses_as("sh-QU", "sr-Latn-EU");
-        parses_as("sh-Qaai", "sr-Zinh");
+    fn test_needs_normalization() {
+        assert!(!needs_normalization("en-US"));
+        assert!(!needs_normalization("zh-Hant-TW"));
+        assert!(needs_normalization("EN_us"));
+        assert!(needs_normalization("en_US"));
+        assert!(needs_normalization("iw"));
+        assert!(needs_normalization("zh-CN"));
+        assert!(needs_normalization("en-Latn-US-x-private"));
+        assert!(!needs_normalization("en-$"));
+    }
+
+    #[test]
+    fn test_equivalent() {
+        assert!(tags_equivalent("zh-CN", "zh-Hans-CN"));
+        assert!(tags_equivalent("EN_us", "en-US"));
+        assert!(tags_equivalent("iw", "he"));
+        assert!(!tags_equivalent("en", "en-US"));
+        assert!(!tags_equivalent("en", "not a tag"));
+        assert!(!tags_equivalent("english", "en"));
+    }
+
+    #[test]
+    fn test_analyze() {
+        let info = analyze("en-Latn-US-x-foo");
+        assert_eq!(info.len(), 5);
+        assert_eq!(info[0],
+                   SubtagInfo {
+                       text: "en".to_string(),
+                       role: SubtagRole::Language,
+                       registered: true,
+                   });
+        assert_eq!(info[1].role, SubtagRole::Script);
+        assert!(info[1].registered);
+        assert_eq!(info[2].role, SubtagRole::Region);
+        assert!(info[2].registered);
+        assert_eq!(info[3].role, SubtagRole::PrivateUse);
+        assert_eq!(info[4].text, "foo");
+        assert_eq!(info[4].role, SubtagRole::PrivateUse);
+        assert!(!info[4].registered);
+
+        let info = analyze("qqq-Qqqq-QQ");
+        assert_eq!(info[0].role, SubtagRole::Language);
+        assert_eq!(info[1].role, SubtagRole::Script);
+        assert_eq!(info[2].role, SubtagRole::Region);
+        assert!(info.iter().all(|subtag| !subtag.registered));
+
+        let info = analyze("iw-UK");
+        assert!(info[0].registered);
+        assert!(info[1].registered);
+    }
+
+    #[test]
+    fn test_language_is() {
+        assert!(lang("en").language_is("en"));
+        assert!(lang("en-US").language_is("EN"));
+        assert!(!lang("en").language_is("eng"));
+        assert!(!lang("en").language_is("fr"));
+        assert!(!lang("en").language_is("not a language"));
+        assert!(languages::UNKNOWN.language_is("und"));
+        assert!(lang("und-TW").language_is("und"));
+        assert!(!lang("en").language_is("und"));
+
+        match languages::UNKNOWN.language_subtag_cow() {
+            Cow::Borrowed(subtag) => assert_eq!(subtag, "und"),
+            Cow::Owned(_) => panic!("expected a borrowed subtag"),
+        }
+        assert_eq!(lang("zh-TW").language_subtag_cow(), "zh");
+    }
+
+    #[test]
+    fn test_subtag_cow() {
+        let codes: Vec<LanguageCode> = languages::ALL.iter()
+            .cloned()
+            .chain(parse_all(vec!["und", "qaa-Qaaa-QM", "sr-Latn-RS", "es-419", "aai-Latn-ZZ",
+                                  "und-Zzzz", "ccp-Cakm-BD"]))
+            .collect();
+        for code in codes {
+            let max = code.maximize();
+            assert_eq!(code.language_subtag_cow(), code.language_subtag());
+            assert_eq!(max.language_subtag_cow(), max.language_subtag());
+            assert_eq!(code.get_script_cow().map(String::from), code.get_script());
+            assert_eq!(max.get_script_cow().map(String::from), max.get_script());
+            assert_eq!(code.get_region_cow().map(String::from), code.get_region());
+            assert_eq!(max.get_region_cow().map(String::from), max.get_region());
+        }
+    }
+
+    #[cfg(feature = "interned-subtags")]
+    #[test]
+    fn test_interned_subtags() {
+        let code = lang("en-Latn-US");
+        let borrowed = |cow: Cow<'static, str>| match cow {
+            Cow::Borrowed(_) => true,
+            Cow::Owned(_) => false,
+        };
+        assert!(borrowed(code.language_subtag_cow()));
+        assert!(borrowed(code.get_script_cow().unwrap()));
+        assert!(borrowed(code.get_region_cow().unwrap()));
+        assert!(borrowed(lang("es-419").get_region_cow().unwrap()));
+        assert!(!borrowed(lang("qaa").language_subtag_cow()));
+    }
+
+    #[test]
+    fn test_with() {
+        assert_eq!(lang("en-US").with_region("GB"), Ok(lang("en-GB")));
+        assert_eq!(lang("en-US").with_region("ZZ"), Ok(lang("en")));
+        assert_eq!(lang("zh-Hant-TW").with_script("Hans"), Ok(lang("zh-Hans-TW")));
+        assert_eq!(lang("zh-Hant-TW").with_language("yue"), Ok(lang("yue-Hant-TW")));
+        assert_eq!(lang("roa-opt-pro").with_language("it"), Ok(lang("it")));
+        assert_eq!(lang("en-US").with_language("und"), Ok(lang("und-US")));
+        assert_eq!(lang("en").with_script("Lat"),
+                   Err(LanguageCodeError::SubtagFormatError("Lat".to_string())));
+        assert!(lang("en").with_region("USA").is_err());
+    }
+
+    #[test]
+    fn test_without() {
+        let code = lang("zh-Hant-TW");
+        assert_eq!(code.without_region(), lang("zh-Hant"));
+        assert_eq!(code.without_script(), lang("zh-TW").without_script());
+        assert_eq!(code.without_script().to_string(), "zh-TW");
+        assert_eq!(code.without_language(), lang("und-Hant-TW"));
+        assert_eq!(lang("ine-pro").without_language(), languages::UNKNOWN);
+        assert_eq!(code.without_language().without_script().without_region(),
+                   languages::UNKNOWN);
+    }
+
+    #[test]
+    fn test_parts_struct() {
+        let parts = lang("zh-Hant-TW").to_parts();
+        assert_eq!(parts,
+                   LanguageParts {
+                       language: Some("zh".to_string()),
+                       extlang: None,
+                       script: Some("Hant".to_string()),
+                       region: Some("TW".to_string()),
+                   });
+        for tag in ["zh-Hant-TW", "en", "und", "und-419", "ine-pro", "roa-opt-pro"].iter() {
+            let code = lang(tag);
+            assert_eq!(LanguageCode::from_parts_struct(&code.to_parts()), Ok(code));
+        }
+
+        let bad_parts = LanguageParts { region: Some("Hant".to_string()), ..parts };
+        assert!(LanguageCode::from_parts_struct(&bad_parts).is_err());
+        let bad_parts = LanguageParts { extlang: Some("Hant".to_string()), ..Default::default() };
+        assert!(LanguageCode::from_parts_struct(&bad_parts).is_err());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_parts_json() {
+        let parts = lang("zh-Hant-TW").to_parts();
+        let json: String = serde_json::to_string(&parts).unwrap();
+        assert_eq!(json, r#"{"language":"zh","script":"Hant","region":"TW"}"#);
+        let parsed: LanguageParts = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed, parts);
     }
 
     #[test]
@@ -523,6 +2752,50 @@ mod tests {
                    languages::PORTUGUESE);
     }
 
+    #[test]
+    fn test_root() {
+        let lcode: LanguageCode = "root".parse().unwrap();
+        assert_eq!(lcode, languages::ROOT);
+        assert!(languages::ROOT.is_root());
+        assert!(languages::UNKNOWN.is_root());
+        assert!(!languages::ENGLISH.is_root());
+    }
+
+    #[test]
+    fn test_only() {
+        let code = lang("zh-Hant-TW");
+        assert_eq!(code.region_only().get_region(), Some("TW".to_string()));
+        assert_eq!(code.region_only().get_language(), None);
+        assert_eq!(code.region_only().get_script(), None);
+        assert_eq!(code.region_only().to_string(), "und-TW");
+
+        assert_eq!(code.script_only().get_script(), Some("Hant".to_string()));
+        assert_eq!(code.script_only().get_language(), None);
+        assert_eq!(code.script_only().get_region(), None);
+        assert_eq!(code.script_only().to_string(), "und-Hant");
+    }
+
+    #[test]
+    fn test_merge() {
+        assert_eq!(lang("en-Latn").merge(lang("und-GB")), lang("en-Latn-GB"));
+        assert_eq!(lang("en-US").merge(lang("und-GB")), lang("en-US"));
+        assert_eq!(lang("und-GB").merge(lang("en-Latn")), lang("en-Latn-GB"));
+        assert_eq!(lang("und").merge(lang("fr-CA")), lang("fr-CA"));
+        assert_eq!(lang("pt-BR").merge(lang("und")), lang("pt-BR"));
+    }
+
+    #[test]
+    fn test_common_ancestor() {
+        assert_eq!(lang("en-US").common_ancestor(lang("en-GB")), lang("en"));
+        assert_eq!(lang("zh-Hant-TW").common_ancestor(lang("zh-Hant-HK")), lang("zh-Hant"));
+        assert_eq!(lang("en").common_ancestor(lang("fr")), lang("und"));
+        assert_eq!(lang("en-CA").common_ancestor(lang("fr-CA")), lang("und-CA"));
+        assert_eq!(lang("sr-Latn").common_ancestor(lang("sr-Latn")), lang("sr-Latn"));
+        assert_eq!(LanguageCode::parse_raw("zh-yue").unwrap()
+                       .common_ancestor(LanguageCode::parse_raw("zh-cmn").unwrap()),
+                   LanguageCode::parse_raw("zh").unwrap());
+    }
+
     #[test]
     fn test_maximize() {
         maximizes_to("en", "en-Latn-US");
@@ -532,6 +2805,28 @@ mod tests {
         maximizes_to("und-Vaii", "vai-Vaii-LR");
     }
 
+    #[test]
+    fn test_maximize_all() {
+        let codes: Vec<LanguageCode> = languages::ALL.iter()
+            .cloned()
+            .chain(vec![lang("und"), lang("und-RU"), lang("qaa"), lang("zh-Hant-TW")])
+            .collect();
+        let maximized = maximize_all(&codes);
+        assert_eq!(maximized.len(), codes.len());
+        for (code, max) in codes.iter().zip(maximized.iter()) {
+            assert_eq!(code.maximize(), *max);
+        }
+        assert_eq!(maximize_all(&[]), vec![]);
+    }
+
+    #[test]
+    fn test_maximize_root() {
+        assert_eq!(languages::UNKNOWN.maximize(), lang("en-Latn-US"));
+        assert_eq!(LanguageCode::new(EMPTY_CODE).maximize(), lang("en-Latn-US"));
+        assert_eq!(LanguageCode::new(ROOT_MAXIMIZED), lang("en-Latn-US"));
+        assert_eq!(langdata::LIKELY_SUBTAGS.get(&EMPTY_CODE), Some(&ROOT_MAXIMIZED));
+    }
+
     #[test]
     fn test_minimize() {
         minimizes_to("en-Latn-US", "en");
@@ -560,6 +2855,610 @@ mod tests {
         check_distance("en", "ja", 124);
     }
 
+    #[test]
+    fn test_all() {
+        assert!(languages::ALL.contains(&languages::FRENCH));
+        assert!(languages::ALL.contains(&languages::TAIWANESE_CHINESE));
+        assert!(!languages::ALL.contains(&languages::UNKNOWN));
+        assert!(!languages::ALL.contains(&languages::INHERIT_SCRIPT));
+        for (i, code) in languages::ALL.iter().enumerate() {
+            assert!(!languages::ALL[..i].contains(code));
+        }
+    }
+
+    #[test]
+    fn test_related_languages() {
+        let related = lang("fr").related_languages(15);
+        assert!(related.contains(&(languages::MALAGASY, 14)));
+        assert!(related.iter().all(|&(code, distance)| code != lang("fr") && distance <= 15));
+        assert!(related.windows(2).all(|pair| pair[0].1 <= pair[1].1));
+        assert!(!related.iter().any(|&(code, _)| code == languages::JAPANESE));
+
+        let related = lang("fr").related_languages(0);
+        assert!(related.iter().all(|&(_, distance)| distance == 0));
+    }
+
+    #[test]
+    fn test_language_distance() {
+        assert_eq!(lang("ms").language_distance(lang("id")), 10);
+        assert_eq!(lang("ms-MY").language_distance(lang("id-ID")), 10);
+        assert_eq!(lang("id").language_distance(lang("ms")), 80);
+        assert_eq!(lang("nb").language_distance(lang("nn")), 10);
+        assert_eq!(lang("nn").language_distance(lang("nb")), 10);
+        assert_eq!(lang("en").language_distance(lang("fr")), 80);
+        assert_eq!(lang("sr-Cyrl-RS").language_distance(lang("sr-Latn-ME")), 0);
+    }
+
+    #[test]
+    fn test_match_distance_premaximized() {
+        let codes = [lang("en"), lang("en-GB"), lang("sr-Cyrl"), lang("sr-Latn"),
+                     lang("zh-TW"), lang("zh"), lang("pt-AO"), lang("zxx")];
+        for &desired in codes.iter() {
+            for &supported in codes.iter() {
+                assert_eq!(desired.maximize().match_distance_premaximized(supported.maximize()),
+                           desired.match_distance(supported));
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    #[cfg(debug_assertions)]
+    fn test_match_distance_premaximized_not_maximal() {
+        lang("en").match_distance_premaximized(lang("en-Latn-GB"));
+    }
+
+    #[test]
+    fn test_known_distances() {
+        let known: Vec<(LanguageCode, LanguageCode, i32)> = known_distances().collect();
+        assert!(known.contains(&(lang("en-Latn-US"), lang("en-Latn-PR"), 4)));
+        assert!(known.contains(&(lang("en-Latn-PR"), lang("en-Latn-US"), 4)));
+        assert!(known.contains(&(languages::MALAGASY, languages::FRENCH, 10)));
+        assert!(!known.iter().any(|&(desired, supported, _)| {
+            desired == languages::FRENCH && supported == languages::MALAGASY
+        }));
+        // en-US to en-GB comes from a general rule, not a listed pair
+        assert!(!known.iter().any(|&(desired, supported, _)| {
+            desired == lang("en-Latn-US") && supported == lang("en-Latn-GB")
+        }));
+        for &(desired, supported, distance) in known.iter().take(20) {
+            let pair = language_pair_bytes(desired.data, supported.data);
+            assert_eq!(langdata::MATCH_DISTANCE.get(&pair), Some(&distance));
+        }
+    }
+
+    #[test]
+    fn test_match_matrix() {
+        let desired = vec![lang("en-US"), lang("zh-Hant")];
+        let supported = vec![lang("en-GB"), lang("zh-Hans"), lang("en")];
+        let matrix = match_matrix(&desired, &supported);
+        assert_eq!(matrix.len(), 2);
+        assert_eq!(matrix[0], vec![6, 124, 0]);
+        assert_eq!(matrix[1][1], 23);
+        assert_eq!(matrix[1][1], lang("zh-Hant").match_distance(lang("zh-Hans")));
+        assert!(match_matrix(&[], &supported).is_empty());
+
+        // zxx only matches itself, in the matrix as everywhere else.
+        let no_content = vec![languages::NO_LINGUISTIC_CONTENT];
+        let matrix = match_matrix(&no_content, &supported);
+        for (&other, &distance) in supported.iter().zip(matrix[0].iter()) {
+            assert_eq!(distance, languages::NO_LINGUISTIC_CONTENT.match_distance(other), "{}", other);
+        }
+        assert_eq!(match_matrix(&no_content, &no_content), vec![vec![0]]);
+
+        // So does the rule for mul.
+        let multiple = vec![languages::MULTIPLE_LANGUAGES];
+        let matrix = match_matrix(&multiple, &supported);
+        for (&other, &distance) in supported.iter().zip(matrix[0].iter()) {
+            assert_eq!(distance, MATCH_DISTANCE_MULTIPLE, "{}", other);
+            assert_eq!(distance, languages::MULTIPLE_LANGUAGES.match_distance(other), "{}", other);
+        }
+    }
+
+    #[cfg(feature = "serde-compact")]
+    fn bincode_options() -> impl bincode::Options {
+        use bincode::Options;
+        bincode::DefaultOptions::new().with_fixint_encoding()
+    }
+
+    #[cfg(feature = "serde-compact")]
+    #[test]
+    fn test_serialize_compact() {
+        let code = lang("zh-Hant-TW");
+        let mut buf: Vec<u8> = Vec::new();
+        as_u64::serialize(&code, &mut bincode::Serializer::new(&mut buf, bincode_options()))
+            .unwrap();
+        assert_eq!(buf.len(), 8);
+
+        let mut de = bincode::Deserializer::from_slice(&buf, bincode_options());
+        assert_eq!(as_u64::deserialize(&mut de).unwrap(), code);
+
+        let bad: Vec<u8> = vec![0xff; 8];
+        let mut de = bincode::Deserializer::from_slice(&bad, bincode_options());
+        assert!(as_u64::deserialize(&mut de).is_err());
+    }
+
+    #[test]
+    fn test_coverage_overlap() {
+        let a = vec![lang("en"), lang("fr")];
+        let b = vec![lang("en-GB"), lang("de")];
+        assert_eq!(coverage_overlap(&a, &b, 25), 0.5);
+        assert_eq!(coverage_overlap(&a, &a, 25), 1.0);
+        assert_eq!(coverage_overlap(&a, &[lang("ja")], 25), 0.0);
+        assert_eq!(coverage_overlap(&a, &[], 25), 0.0);
+        assert_eq!(coverage_overlap(&[], &[], 25), 1.0);
+
+        // en-GB is 6 away from en, so it's only covered with a looser cutoff.
+        assert_eq!(coverage_overlap(&a, &b, 5), 0.0);
+    }
+
+    #[test]
+    fn test_forbid_script_mismatch() {
+        let mut config = MatcherConfig::new();
+        assert_eq!(config.match_distance(lang("sr-Cyrl"), lang("sr-Latn")), 5);
+        assert_eq!(config.match_supported_with_cutoff(lang("sr-Cyrl"), 25, &[lang("sr-Latn")]),
+                   (lang("sr-Latn"), 5));
+
+        config.forbid_script_mismatch = true;
+        assert!(config.match_distance(lang("sr-Cyrl"), lang("sr-Latn")) >= 1000);
+        assert_eq!(config.match_supported_with_cutoff(lang("sr-Cyrl"), 25, &[lang("sr-Latn")]),
+                   (languages::UNKNOWN, 1000));
+        assert_eq!(config.match_distance(lang("sr-Cyrl"), lang("sr-Cyrl-ME")), 4);
+        assert_eq!(config.match_distance(lang("en-US"), lang("en-GB")), 6);
+    }
+
+    #[test]
+    fn test_norwegian_unified() {
+        let norwegian = [lang("no"), lang("nb"), lang("nn"), lang("nn-NO")];
+        let mut config = MatcherConfig::new();
+        assert_eq!(config.match_distance(lang("nb"), lang("no")), 0);
+        assert_eq!(config.match_distance(lang("nn"), lang("nb")), 10);
+        assert_eq!(config.match_distance(lang("no"), lang("nn")), 10);
+        assert_eq!(config.match_supported_with_cutoff(lang("nn"), 5, &[lang("nb")]).0,
+                   languages::UNKNOWN);
+
+        config.norwegian_unified = true;
+        for &desired in norwegian.iter() {
+            for &supported in norwegian.iter() {
+                assert_eq!(config.match_distance(desired, supported), 0);
+            }
+        }
+        assert_eq!(config.match_supported_with_cutoff(lang("nn"), 5, &[lang("nb")]),
+                   (lang("nb"), 0));
+        assert_eq!(config.match_distance(lang("nn"), lang("da")),
+                   MatcherConfig::new().match_distance(lang("nb"), lang("da")));
+        assert_eq!(config.match_distance(lang("en-US"), lang("en-GB")), 6);
+    }
+
+    #[test]
+    fn test_script_distances() {
+        let mut config = MatcherConfig::new();
+        assert_eq!(config.match_distance(lang("sr-Cyrl"), lang("sr-Latn")), 5);
+        let chinese = config.match_distance(lang("zh-Hant"), lang("zh-Hans"));
+
+        config.script_distances.insert((lang("sr-Cyrl"), lang("sr-Latn")), 2);
+        assert_eq!(config.match_distance(lang("sr-Cyrl"), lang("sr-Latn")), 2);
+        assert_eq!(config.match_distance(lang("sr-Cyrl-ME"), lang("sr-Latn-ME")), 2);
+        assert_eq!(config.match_distance(lang("sr-Latn"), lang("sr-Cyrl")), 5);
+        assert_eq!(config.match_distance(lang("zh-Hant"), lang("zh-Hans")), chinese);
+    }
+
+    #[test]
+    fn test_default_regions() {
+        let supported = [lang("en-US"), lang("en-GB")];
+        let mut config = MatcherConfig::new();
+        assert_eq!(config.match_distance(lang("en"), lang("en-GB")), 6);
+        assert_eq!(config.match_supported_with_cutoff(lang("en"), 25, &supported),
+                   (lang("en-US"), 0));
+
+        config.default_regions.insert(lang("en"), lang("en-GB"));
+        assert_eq!(config.match_distance(lang("en"), lang("en-GB")), 0);
+        assert_eq!(config.match_distance(lang("en-Latn"), lang("en-GB")), 0);
+        assert_eq!(config.match_supported_with_cutoff(lang("en"), 25, &supported),
+                   (lang("en-GB"), 0));
+        // Desired codes with a region, and other languages, are unaffected
+        assert_eq!(config.match_distance(lang("en-US"), lang("en-GB")), 6);
+        assert_eq!(config.match_distance(lang("fr"), lang("fr-FR")), 0);
+    }
+
+    #[test]
+    fn test_parse_debug_form() {
+        assert_eq!(LanguageCode::parse_debug_form("en-US"), Ok(lang("en-US")));
+        assert_eq!(LanguageCode::parse_debug_form("lang(\"en-US\")"), Ok(lang("en-US")));
+        assert_eq!(LanguageCode::parse_debug_form(" lang(\"iw\")\n"), Ok(lang("he")));
+        let code = lang("zh-Hant-TW");
+        assert_eq!(LanguageCode::parse_debug_form(&code.to_string()), Ok(code));
+        assert!(LanguageCode::parse_debug_form("lang(\"en-US").is_err());
+    }
+
+    #[test]
+    fn test_root_default() {
+        let mut config = MatcherConfig::new();
+        assert_eq!(config.root_default, lang("en-Latn-US"));
+        assert_eq!(config.maximize(lang("und")), lang("en-Latn-US"));
+        config.root_default = lang("es");
+        assert_eq!(config.maximize(lang("und")), lang("es-Latn-ES"));
+        assert_eq!(config.maximize(lang("und-MX")), lang("es-Latn-MX"));
+        assert_eq!(config.maximize(lang("und-RU")), lang("ru-Cyrl-RU"));
+        assert_eq!(config.maximize(lang("fr")), lang("fr-Latn-FR"));
+        assert_eq!(config.match_distance(lang("und"), lang("es-ES")), 0);
+        assert_eq!(lang("und").maximize(), lang("en-Latn-US"));
+    }
+
+    #[test]
+    fn test_common_language_id() {
+        for &code in &[languages::ENGLISH, languages::SPANISH, languages::CHINESE,
+                       languages::NORWEGIAN_BOKMAL, languages::FILIPINO, languages::CANTONESE] {
+            let id = code.common_language_id().unwrap();
+            assert_eq!(LanguageCode::from_common_language_id(id), Some(code));
+        }
+        assert_eq!(lang("aa").common_language_id(), Some(1));
+        assert_eq!(lang("zu").common_language_id(), Some(198));
+        assert_eq!(lang("ace").common_language_id(), Some(199));
+        assert_eq!(LanguageCode::from_common_language_id(499), Some(lang("zza")));
+        assert_eq!(LanguageCode::from_common_language_id(500), None);
+        assert_eq!(lang("zh-Hant-TW").common_language_id(), lang("zh").common_language_id());
+        assert_eq!(lang("und").common_language_id(), None);
+        assert_eq!(lang("qaa").common_language_id(), None);
+        assert_eq!(LanguageCode::from_common_language_id(0), None);
+        assert_eq!(LanguageCode::from_common_language_id(u16::MAX), None);
+    }
+
+    #[test]
+    fn test_is_deprecated_form_of() {
+        let raw = |tag| LanguageCode::parse_raw(tag).unwrap();
+        assert!(raw("iw").is_deprecated_form_of(lang("he")));
+        assert!(raw("de-DD").is_deprecated_form_of(lang("de-DE")));
+        assert!(!lang("he").is_deprecated_form_of(lang("he")));
+        assert!(!lang("he").is_deprecated_form_of(raw("iw")));
+        assert!(!lang("en").is_deprecated_form_of(lang("fr")));
+    }
+
+    #[test]
+    fn test_region_as_numeric_code() {
+        let raw = |tag| LanguageCode::parse_raw(tag).unwrap();
+        assert_eq!(lang("en-US").region_as_numeric_code(), Some(raw("en-840")));
+        assert_eq!(lang("es-419").region_as_numeric_code(), Some(lang("es-419")));
+        assert_eq!(lang("sr-Latn-RS").region_as_numeric_code(), Some(raw("sr-Latn-688")));
+        assert_eq!(lang("de-DE").region_as_numeric_code(), Some(raw("de-276")));
+        assert_eq!(lang("fr-FR").region_as_numeric_code(), Some(raw("fr-250")));
+        assert_eq!(lang("en").region_as_numeric_code(), None);
+        assert_eq!(lang("en-US").region_as_numeric_code().unwrap().to_string(), "en-840");
+        assert!(lang("en-US").region_as_numeric_code().unwrap().is_equivalent(lang("en-US")));
+    }
+
+    #[test]
+    fn test_parse_onto() {
+        let base = lang("en-Latn-US");
+        assert_eq!(base.parse_onto("GB"), Ok(lang("en-Latn-GB")));
+        assert_eq!(base.parse_onto("-GB"), Ok(lang("en-Latn-GB")));
+        assert_eq!(base.parse_onto("_gb"), Ok(lang("en-Latn-GB")));
+        assert_eq!(base.parse_onto("419"), Ok(lang("en-Latn-419")));
+        assert_eq!(base.parse_onto("Dsrt"), Ok(lang("en-Dsrt-US")));
+        assert_eq!(base.parse_onto("fr"), Ok(lang("fr-Latn-US")));
+        assert_eq!(base.parse_onto("fr-CA"), Ok(lang("fr-Latn-CA")));
+        assert_eq!(base.parse_onto("iw"), Ok(lang("he-Latn-US")));
+        assert_eq!(base.parse_onto(""), Ok(base));
+        assert!(base.parse_onto("-G!").is_err());
+    }
+
+    #[test]
+    fn test_language_shard() {
+        assert_eq!(lang("en-US").language_shard(16), lang("en-GB").language_shard(16));
+        assert_eq!(lang("zh-Hant-TW").language_shard(7), lang("zh").language_shard(7));
+        assert_eq!(lang("fr").language_shard(1), 0);
+        // These values must never change, or data would move between shards
+        assert_eq!(lang("en").language_shard(1000), 567);
+        assert_eq!(lang("ja").language_shard(1000), 611);
+
+        let mut counts = vec![0; 8];
+        for code in languages::ALL {
+            counts[code.language_shard(8) as usize] += 1;
+        }
+        let expected = languages::ALL.len() / 8;
+        for &count in &counts {
+            assert!(count > expected / 2 && count < expected * 2,
+                    "uneven shards: {:?}", counts);
+        }
+    }
+
+    #[test]
+    fn test_normalization_diff() {
+        let pairs = |list: &[(&str, &str)]| -> Vec<(String, String)> {
+            list.iter().map(|&(a, b)| (a.to_string(), b.to_string())).collect()
+        };
+        assert_eq!(normalization_diff("en-US"), vec![]);
+        assert_eq!(normalization_diff("EN_us"), pairs(&[("EN", "en"), ("us", "US")]));
+        assert_eq!(normalization_diff("iw"), pairs(&[("iw", "he")]));
+        assert_eq!(normalization_diff("zh-CN"), pairs(&[("", "Hans")]));
+        assert_eq!(normalization_diff("de-DD"), pairs(&[("DD", "DE")]));
+        assert_eq!(normalization_diff("en-US-u-ca-gregory"),
+                   pairs(&[("u", ""), ("ca", ""), ("gregory", "")]));
+        assert_eq!(normalization_diff("not a tag"), vec![]);
+        assert_eq!(normalization_diff("abcd"), vec![]);
+        assert!(!needs_normalization("abcd"));
+        for &tag in &["EN-us", "iw", "zh-CN", "en-US", "sr-Latn-RS"] {
+            assert_eq!(normalization_diff(tag).is_empty(), !needs_normalization(tag));
+        }
+    }
+
+    #[test]
+    fn test_language_for_script() {
+        assert_eq!(language_for_script(lang("und-Hang")), Some(lang("ko")));
+        assert_eq!(language_for_script(lang("und-Hira")), Some(lang("ja")));
+        assert_eq!(language_for_script(lang("und-Cyrl")), Some(lang("ru")));
+        assert_eq!(language_for_script(lang("sr-Cyrl-RS")), Some(lang("ru")));
+        assert_eq!(language_for_script(lang("und-Latn")), None);
+        assert_eq!(language_for_script(lang("en")), None);
+        assert_eq!(language_for_script(lang("und-Qaaa")), None);
+    }
+
+    #[test]
+    fn test_alias_implies_script() {
+        // "sh" (Serbo-Croatian) is replaced by "sr-Latn", not just "sr", so
+        // it maximizes to Latin script, where plain "sr" would be Cyrillic.
+        assert_eq!(lang("sh"), lang("sr-Latn"));
+        assert_eq!(lang("sh").maximize(), lang("sr-Latn-RS"));
+        assert_eq!(lang("sr").maximize(), lang("sr-Cyrl-RS"));
+        assert_eq!(lang("hbs-BA").maximize(), lang("sr-Latn-BA"));
+
+        // A script in the original tag wins over the one the alias implies.
+        assert_eq!(lang("sh-Cyrl"), lang("sr-Cyrl"));
+
+        // Aliases can imply a region in the same way.
+        assert_eq!(lang("swc").maximize(), lang("sw-Latn-CD"));
+        assert_eq!(lang("sw").maximize(), lang("sw-Latn-TZ"));
+        assert_eq!(lang("mo").maximize(), lang("ro-Latn-MD"));
+    }
+
+    #[test]
+    fn test_scripts_compatible() {
+        assert!(scripts_compatible(lang("und-Hira"), lang("und-Kana")));
+        assert!(scripts_compatible(lang("ja-Jpan"), lang("und-Hani")));
+        assert!(scripts_compatible(lang("ko-Hang"), lang("zh-Hant")));
+        assert!(scripts_compatible(lang("und-Latn"), lang("en-Latn-US")));
+        assert!(scripts_compatible(lang("und-Zyyy"), lang("und-Arab")));
+        assert!(scripts_compatible(lang("und-Cyrl"), languages::INHERIT_SCRIPT));
+        assert!(!scripts_compatible(lang("und-Latn"), lang("und-Cyrl")));
+        assert!(!scripts_compatible(lang("und-Hira"), lang("und-Hang")));
+        assert!(scripts_compatible(lang("und-Hani"), lang("zh-Hans")));
+        assert!(scripts_compatible(lang("zh-Hant"), lang("und-Bopo")));
+        assert!(!scripts_compatible(lang("zh-Hans"), lang("zh-Hant")));
+        assert!(!scripts_compatible(lang("zh-Hans"), lang("und-Bopo")));
+        assert!(!scripts_compatible(lang("en"), lang("en")));
+        assert!(scripts_compatible(lang("en").maximize(), lang("en").maximize()));
+    }
+
+    #[test]
+    fn test_normalize_pair() {
+        for tag in &["en", "en-US", "en-GB", "zh-TW", "zh-Hans", "sr-Latn", "und", "und-RU", "qaa"] {
+            let code = lang(tag);
+            assert_eq!(code.normalize_pair(), (code.minimize(), code.maximize()));
+        }
+        assert_eq!(lang("zh-Hant-TW").normalize_pair(), (lang("zh-Hant"), lang("zh-Hant-TW")));
+    }
+
+    #[test]
+    fn test_parse_options() {
+        let mut options = ParseOptions::new();
+        assert_eq!(options.parse("en_US"), Ok(lang("en-US")));
+        assert!(options.parse("en_US.UTF-8").is_err());
+        options.posix_tolerant = true;
+        assert_eq!(options.parse("en_US.UTF-8"), Ok(lang("en-US")));
+        assert_eq!(options.parse("pt_BR.ISO-8859-1"), Ok(lang("pt-BR")));
+        assert_eq!(options.parse("en_US"), Ok(lang("en-US")));
+    }
+
+    #[test]
+    fn test_region_affects_script() {
+        assert!(lang("zh").region_affects_script());
+        assert!(lang("zh-Hant-TW").region_affects_script());
+        assert!(lang("sr").region_affects_script());
+        assert!(lang("pa").region_affects_script());
+        assert!(!lang("en").region_affects_script());
+        assert!(!lang("fr-CA").region_affects_script());
+        assert!(!lang("ja").region_affects_script());
+    }
+
+    #[test]
+    fn test_likely_subtags() {
+        let pairs: HashMap<LanguageCode, LanguageCode> = likely_subtags().collect();
+        assert_eq!(pairs.get(&lang("und")), Some(&lang("en-Latn-US")));
+        assert_eq!(pairs.get(&lang("und-Hang")), Some(&lang("ko-Hang-KR")));
+        assert_eq!(pairs.get(&LanguageCode::parse_raw("zh-TW").unwrap()), Some(&lang("zh-Hant-TW")));
+        assert_eq!(pairs.len(), langdata::LIKELY_SUBTAGS.len());
+        for (from, to) in pairs {
+            assert_eq!(from.maximize(), to);
+        }
+    }
+
+    #[test]
+    fn test_language_family() {
+        assert_eq!(lang("fr").language_family(), Some(Family::Romance));
+        assert_eq!(lang("pt-BR").language_family(), Some(Family::Romance));
+        assert_eq!(lang("ru").language_family(), Some(Family::Slavic));
+        assert_eq!(lang("ja").language_family(), Some(Family::Japonic));
+        assert_eq!(lang("zh-Hant").language_family(), Some(Family::Sinitic));
+        assert_eq!(lang("iw").language_family(), Some(Family::Semitic));
+        assert_eq!(lang("eu").language_family(), Some(Family::Isolate));
+        assert_eq!(lang("la").language_family(), Some(Family::Romance));
+        assert_eq!(lang("und").language_family(), None);
+        assert_eq!(lang("qaa").language_family(), None);
+        for code in (1..).map(LanguageCode::from_common_language_id).take_while(|c| c.is_some()) {
+            let code = code.unwrap();
+            assert!(code.language_family().is_some(), "{}", code);
+        }
+    }
+
+    #[test]
+    fn test_distance_to_self() {
+        let codes = languages::ALL.iter()
+            .cloned()
+            .chain(parse_all(vec!["und", "mul", "zxx", "qaa", "und-Latn", "und-419", "zh-Hant"]))
+            .chain(likely_subtags().map(|(from, _)| from));
+        for code in codes {
+            assert_eq!(code.match_distance(code), 0, "{}", code);
+            assert_eq!(MatcherConfig::new().match_distance(code, code), 0, "{}", code);
+        }
+    }
+
+    #[test]
+    fn test_symmetric_cjk() {
+        let mut config = MatcherConfig::new();
+        assert_eq!(config.match_distance(lang("zh-Hans-CN"), lang("zh-Hant-CN")), 15);
+        assert_eq!(config.match_distance(lang("zh-Hant-CN"), lang("zh-Hans-CN")), 19);
+        assert_eq!(config.match_distance(lang("zh-Hans"), lang("zh-Hant")), 19);
+        assert_eq!(config.match_distance(lang("zh-Hant"), lang("zh-Hans")), 23);
+        config.symmetric_cjk = true;
+        assert_eq!(config.match_distance(lang("zh-Hans-CN"), lang("zh-Hant-CN")), 19);
+        assert_eq!(config.match_distance(lang("zh-Hant-CN"), lang("zh-Hans-CN")), 19);
+        assert_eq!(config.match_distance(lang("zh-Hans"), lang("zh-Hant")), 23);
+        assert_eq!(config.match_distance(lang("zh-Hant"), lang("zh-Hans")), 23);
+        assert_eq!(config.match_distance(lang("zh-Hant"), lang("zh-Hant-HK")),
+                   MatcherConfig::new().match_distance(lang("zh-Hant"), lang("zh-Hant-HK")));
+    }
+
+    #[test]
+    fn test_match_quality() {
+        assert_eq!(match_quality(0), MatchQuality::Exact);
+        assert_eq!(match_quality(6), MatchQuality::Excellent);
+        assert_eq!(match_quality(19), MatchQuality::Good);
+        assert_eq!(match_quality(46), MatchQuality::Poor);
+        assert_eq!(match_quality(124), MatchQuality::Unusable);
+        assert_eq!(match_quality(lang("en-GB").match_distance(lang("en-GB"))),
+                   MatchQuality::Exact);
+    }
+
+    #[test]
+    fn test_classify_distance() {
+        assert_eq!(classify_distance(0), DistanceClass::Minor);
+        assert_eq!(classify_distance(10), DistanceClass::Minor);
+        assert_eq!(classify_distance(11), DistanceClass::Comprehensible);
+        assert_eq!(classify_distance(25), DistanceClass::Comprehensible);
+        assert_eq!(classify_distance(26), DistanceClass::Distant);
+        assert_eq!(classify_distance(123), DistanceClass::Distant);
+        assert_eq!(classify_distance(124), DistanceClass::Unrelated);
+        assert_eq!(classify_distance(1000), DistanceClass::Unrelated);
+        assert_eq!(classify_distance(lang("en").match_distance(lang("ja"))),
+                   DistanceClass::Unrelated);
+    }
+
+    #[test]
+    fn test_coverage_score() {
+        let demand = [(lang("en-GB"), 500), (lang("pt-BR"), 300), (lang("de-AT"), 200)];
+        let supported = [lang("en"), lang("de")];
+        assert_eq!(coverage_score(&supported, &demand, 25), 700);
+        assert_eq!(coverage_score(&[lang("en"), lang("de"), lang("pt")], &demand, 25), 1000);
+        assert_eq!(coverage_score(&supported, &demand, 5), 200);
+        assert_eq!(coverage_score(&[], &demand, 25), 0);
+        assert_eq!(coverage_score(&supported, &[], 25), 0);
+    }
+
+    #[test]
+    fn test_best_addition() {
+        let supported = [lang("en"), lang("es")];
+        let demand = [(lang("en-GB"), 500), (lang("pt-BR"), 300), (lang("gl"), 40),
+                      (lang("de-AT"), 200), (lang("de-CH"), 150), (lang("ca"), 100)];
+        let candidates = [lang("pt"), lang("de"), lang("fr")];
+        // German would cover de-AT and de-CH, more than Portuguese would cover
+        assert_eq!(best_addition(&supported, &demand, &candidates), Some(lang("de")));
+        assert_eq!(best_addition(&supported, &demand, &[lang("fr"), lang("pt")]), Some(lang("pt")));
+        assert_eq!(best_addition(&supported, &demand, &[lang("fr"), lang("en-GB")]), None);
+        assert_eq!(best_addition(&supported, &demand, &[]), None);
+    }
+
+    #[test]
+    fn test_contains_exact() {
+        let supported = vec![lang("en"), lang("zh-Hant"), lang("pt-BR")];
+        assert!(contains_exact(&supported, lang("zh-Hant")));
+        assert!(!contains_exact(&supported, lang("en-US")));
+        assert!(!contains_exact(&supported, lang("pt")));
+
+        let mut set = SupportedSet::new(&supported);
+        assert!(set.contains_exact(lang("zh-Hant")));
+        assert!(set.contains_exact(languages::BRAZILIAN_PORTUGUESE));
+        assert!(!set.contains_exact(lang("en-US")));
+        set.insert(lang("en-US"));
+        assert!(set.contains_exact(lang("en-US")));
+        assert!(!SupportedSet::default().contains_exact(lang("en")));
+    }
+
+    #[test]
+    fn test_interner() {
+        let mut interner = TagInterner::new();
+        for _ in 0..3 {
+            for tag in ["en", "zh-CN", "iw", "EN_us", "sh-Qaai"].iter() {
+                assert_eq!(interner.parse(tag), LanguageCode::parse(tag));
+            }
+        }
+        assert_eq!(interner.cache.len(), 5);
+        assert_eq!(interner.parse("en-"), LanguageCode::parse("en-"));
+        assert_eq!(interner.cache.len(), 5);
+    }
+
+    #[test]
+    fn test_match_lists() {
+        let supported = vec![lang("zh-Hans"), lang("zh-Hant")];
+
+        // A script-specific preference wins over a later generic one, and
+        // vice versa.
+        assert_eq!(match_lists_with_cutoff(5, 25, &[lang("zh-Hant"), lang("zh")], &supported),
+                   (lang("zh-Hant"), 0));
+        assert_eq!(match_lists_with_cutoff(5, 25, &[lang("zh"), lang("zh-Hant")], &supported),
+                   (lang("zh"), 0));
+
+        // Regions imply scripts.
+        assert_eq!(match_lists_with_cutoff(5, 25, &[lang("zh-TW"), lang("zh")], &supported),
+                   (lang("zh-TW"), 0));
+        assert_eq!(match_lists_with_cutoff(5, 25, &[lang("zh-HK")], &supported),
+                   (lang("zh-HK"), 4));
+
+        // A close match for the first preference beats an exact match for
+        // a later one, but a poor match doesn't.
+        let supported = vec![lang("zh-Hant-TW"), lang("zh-Hans")];
+        assert_eq!(match_lists_with_cutoff(5, 25, &[lang("zh-Hant-HK"), lang("zh")], &supported),
+                   (lang("zh-Hant-HK"), 4));
+        assert_eq!(match_lists_with_cutoff(5, 25, &[lang("zh-Hant-HK"), lang("zh")],
+                                           &[lang("zh-Hans")]),
+                   (lang("zh"), 0));
+
+        assert_eq!(match_lists_with_cutoff(5, 25, &[lang("ja")], &supported),
+                   (languages::UNKNOWN, 1000));
+
+        // The first exact match is the answer, even with no rank penalty and
+        // other exact matches later in the list.
+        let desired = [lang("de-AT"), lang("en-US"), lang("fr")];
+        assert_eq!(match_lists_with_cutoff(0, 25, &desired, languages::ALL),
+                   (lang("de-AT"), 0));
+        let desired = [lang("qaa"), lang("fr-CA"), lang("en")];
+        assert_eq!(match_lists_with_cutoff(5, 25, &desired, languages::ALL),
+                   (lang("fr-CA"), 0));
+
+        // Even an exact match has to be within the cutoff.
+        assert_eq!(match_lists_with_cutoff(5, 0, &desired, languages::ALL),
+                   (languages::UNKNOWN, 1000));
+    }
+
+    #[test]
+    fn test_match_lists_supported() {
+        let supported = vec![lang("zh-Hans"), lang("zh-Hant")];
+        assert_eq!(match_lists_supported_with_cutoff(5, 25, &[lang("zh-Hant"), lang("zh")],
+                                                     &supported),
+                   (lang("zh-Hant"), 0));
+        assert_eq!(match_lists_supported_with_cutoff(5, 25, &[lang("zh"), lang("zh-Hant")],
+                                                     &supported),
+                   (lang("zh-Hans"), 0));
+        assert_eq!(match_lists_supported_with_cutoff(5, 25, &[lang("zh-HK")], &supported),
+                   (lang("zh-Hant"), 4));
+
+        let supported = vec![lang("zh-Hant-TW"), lang("zh-Hans")];
+        assert_eq!(match_lists_supported_with_cutoff(5, 25, &[lang("zh-Hant-HK"), lang("zh")],
+                                                     &supported),
+                   (lang("zh-Hant-TW"), 4));
+        assert_eq!(match_lists_supported_with_cutoff(5, 25, &[lang("ja")], &supported),
+                   (languages::UNKNOWN, 1000));
+    }
+
     #[test]
     fn test_distance_named() {
         assert_eq!(languages::NORWEGIAN_BOKMAL.match_distance(languages::NORWEGIAN),
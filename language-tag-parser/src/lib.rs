@@ -1,3 +1,4 @@
+use std::fmt;
 use std::mem::transmute;
 
 pub const LANGUAGE_MASK: u64 = 0x7fff_0000_0000_0000_u64;
@@ -15,6 +16,47 @@ pub const INHERIT_SCRIPT_OLD: u64 = 0x0000000044408800_u64;
 pub const INHERIT_SCRIPT: u64 = 0x00000000694d8000_u64;
 pub const MISSING_CODE: u64 = 1916703853911212032u64;
 
+/// Where one field of an encoded tag lives: `(val & mask) >> shift` extracts
+/// the field's value.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub struct FieldLayout {
+    pub mask: u64,
+    pub shift: u64,
+}
+
+/// The bit layout of an encoded tag. The `proto` field is a single flag bit
+/// that is set when the tag's extlang is really a subtag reserved for
+/// proto-languages.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub struct TagLayout {
+    pub language: FieldLayout,
+    pub proto: FieldLayout,
+    pub extlang: FieldLayout,
+    pub script: FieldLayout,
+    pub region: FieldLayout,
+}
+
+pub const LAYOUT: TagLayout = TagLayout {
+    language: FieldLayout { mask: LANGUAGE_MASK, shift: LANGUAGE_SHIFT },
+    proto: FieldLayout { mask: PROTO_MASK, shift: 47u64 },
+    extlang: FieldLayout { mask: EXTLANG_MASK, shift: EXTLANG_SHIFT },
+    script: FieldLayout { mask: SCRIPT_MASK, shift: SCRIPT_SHIFT },
+    region: FieldLayout { mask: REGION_MASK, shift: 0u64 },
+};
+
+impl TagLayout {
+    /// The fields in order from the most significant bits to the least.
+    pub fn fields(&self) -> [(&'static str, FieldLayout); 5] {
+        [
+            ("language", self.language),
+            ("proto", self.proto),
+            ("extlang", self.extlang),
+            ("script", self.script),
+            ("region", self.region),
+        ]
+    }
+}
+
 /// There are three ranges of values a subtag could be
 /// encoded as:
 ///
@@ -26,22 +68,57 @@ pub const MISSING_CODE: u64 = 1916703853911212032u64;
 fn decode_subtag(val: u64) -> Option<String> {
     if val == 0 {
         None
-    } else if val < 1000 {
-        Some(format!("{number:>0width$}", number = val, width = 3))
     } else {
-        let mut chars: Vec<char> = Vec::with_capacity(4);
-        let mut remain = val - 1000;
-        while remain > 0 {
-            let charnum: u64 = remain % 32;
+        let mut subtag = String::with_capacity(4);
+        write_subtag(&mut subtag, val, SubtagCase::Lower).unwrap();
+        Some(subtag)
+    }
+}
+
+/// How to capitalize a decoded subtag: languages are lowercase, scripts are
+/// titlecase, and regions are uppercase.
+#[derive(PartialEq, Clone, Copy)]
+enum SubtagCase {
+    Lower,
+    Title,
+    Upper,
+}
+
+/// Decode a nonzero subtag value, as described for `decode_subtag`, and
+/// write it in the given case.
+fn write_subtag<W: fmt::Write>(out: &mut W, val: u64, case: SubtagCase) -> fmt::Result {
+    if val < 1000 {
+        out.write_char((b'0' + (val / 100) as u8) as char)?;
+        out.write_char((b'0' + (val / 10 % 10) as u8) as char)?;
+        out.write_char((b'0' + (val % 10) as u8) as char)?;
+    } else {
+        let remain: u64 = val - 1000;
+        // Find the position of the first letter, then read letters
+        // from there until we reach the padding.
+        let mut shift: u64 = 0;
+        while remain >> (shift + 5) > 0 {
+            shift += 5;
+        }
+        let mut first: bool = true;
+        loop {
+            let charnum: u64 = (remain >> shift) % 32;
             if charnum > 0 {
-                let ch = (96u64 + charnum) as u8 as char;
-                chars.push(ch);
+                let base: u64 = if case == SubtagCase::Upper ||
+                                   (case == SubtagCase::Title && first) {
+                    64
+                } else {
+                    96
+                };
+                out.write_char((base + charnum) as u8 as char)?;
+                first = false;
+            }
+            if shift == 0 {
+                break;
             }
-            remain >>= 5;
+            shift -= 5;
         }
-        chars.reverse();
-        Some(chars.into_iter().collect::<String>())
     }
+    Ok(())
 }
 
 /// Encode a subtag using the scheme described for `decode_subtag`.
@@ -55,7 +132,7 @@ fn encode_subtag(subtag: &str, length: usize) -> u64 {
             let mut val: u64 = 0;
             for ch in subtag.chars() {
                 val <<= 5;
-                val += ((ch as u8) - 96u8) as u64;
+                val += ((ch.to_ascii_lowercase() as u8) - 96u8) as u64;
             }
             val <<= 5 * (length - subtag.len());
             val + 1000
@@ -75,6 +152,27 @@ pub enum LanguageCodeError {
 
     // We can't even parse a subtag from here
     ParseError(String),
+
+    // The tag contained this non-ASCII character, such as an accented
+    // letter, which can never appear in a language tag
+    NonAsciiInput(char),
+}
+
+/// The role that a subtag plays in a language tag, as determined by its
+/// shape and position.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum SubtagRole {
+    Language,
+    Extlang,
+    Script,
+    Region,
+    Variant,
+    // A singleton such as "u" or "t", or a subtag that follows one
+    Extension,
+    // The singleton "x", or a subtag that follows it
+    PrivateUse,
+    // A subtag that the parser would reject here
+    Unknown,
 }
 
 #[derive(PartialEq)]
@@ -87,6 +185,10 @@ enum ParserState {
 
 
 fn parse_lowercase_tag(tag: &str) -> Result<u64, LanguageCodeError> {
+    // CLDR calls the base locale "root", which means the same as "und".
+    if tag == "root" {
+        return Ok(EMPTY_CODE);
+    }
     let mut parts = tag.split("-");
     let mut val: u64 = 0;
 
@@ -99,6 +201,11 @@ fn parse_lowercase_tag(tag: &str) -> Result<u64, LanguageCodeError> {
             if !check_characters(language_ref) {
                 return Err(LanguageCodeError::InvalidCharacter(tag.to_string()));
             }
+            // Only 2- and 3-letter languages fit in the encoding. Longer
+            // ones are reserved or rarely registered, so we reject them.
+            if !is_language(language_ref) {
+                return Err(LanguageCodeError::SubtagFormatError(tag.to_string()));
+            }
             val |= encode_subtag(language_ref, 3) << LANGUAGE_SHIFT;
         }
         None => {
@@ -152,7 +259,65 @@ fn parse_lowercase_tag(tag: &str) -> Result<u64, LanguageCodeError> {
     Ok(val)
 }
 
+/// Split a tag into its subtags and classify the role of each one, without
+/// stopping at the first problem. This follows the same rules as parsing,
+/// but subtags that wouldn't parse are marked as `SubtagRole::Unknown`
+/// instead of causing an error. The subtags keep their original case.
+pub fn classify_subtags(tag: &str) -> Vec<(String, SubtagRole)> {
+    let mut result: Vec<(String, SubtagRole)> = Vec::new();
+    let mut state: ParserState = ParserState::AfterLanguage(0);
+    let mut extension_role: Option<SubtagRole> = None;
+    for (i, subtag) in tag.split(&['-', '_'][..]).enumerate() {
+        let subtag_lower: String = subtag.to_lowercase();
+        let subtag_ref: &str = &subtag_lower;
+        let language_state: i32 = {
+            match state {
+                ParserState::AfterLanguage(num) => num,
+                _ => -1,
+            }
+        };
+        let role: SubtagRole = if !check_characters(subtag_ref) || subtag_ref.is_empty() {
+            SubtagRole::Unknown
+        } else if subtag_ref == "x" {
+            extension_role = Some(SubtagRole::PrivateUse);
+            SubtagRole::PrivateUse
+        } else if extension_role == Some(SubtagRole::PrivateUse) {
+            SubtagRole::PrivateUse
+        } else if subtag_ref.len() == 1 && i > 0 {
+            extension_role = Some(SubtagRole::Extension);
+            SubtagRole::Extension
+        } else if let Some(role) = extension_role {
+            role
+        } else if i == 0 {
+            if subtag_ref == "root" ||
+               is_language(subtag_ref) {
+                SubtagRole::Language
+            } else {
+                SubtagRole::Unknown
+            }
+        } else if state != ParserState::AfterVariant && is_variant(subtag_ref) {
+            state = ParserState::AfterVariant;
+            SubtagRole::Variant
+        } else if (language_state >= 0 || state == ParserState::AfterScript) &&
+                  is_region(subtag_ref) {
+            state = ParserState::AfterRegion;
+            SubtagRole::Region
+        } else if language_state >= 0 && is_script(subtag_ref) {
+            state = ParserState::AfterScript;
+            SubtagRole::Script
+        } else if (0..3).contains(&language_state) && is_extlang(subtag_ref) {
+            state = ParserState::AfterLanguage(language_state + 1);
+            SubtagRole::Extlang
+        } else {
+            SubtagRole::Unknown
+        };
+        result.push((subtag.to_string(), role));
+    }
+    result
+}
+
 pub fn encode_tag(tag: &str) -> Result<u64, LanguageCodeError> {
+    check_ascii(tag)?;
     let normal_tag: String = tag.replace("_", "-").to_lowercase();
     Ok(parse_lowercase_tag(&normal_tag)?)
 }
@@ -197,21 +362,80 @@ pub fn decode_region(val: u64) -> Option<String> {
 }
 
 pub fn decode_tag(val: u64) -> String {
-    let mut parts: Vec<String> = Vec::with_capacity(4);
-    parts.push(decode_language(val));
-    match decode_extlang(val) {
-        Some(extlang) => parts.push(extlang),
-        None => {}
+    let mut tag = String::with_capacity(16);
+    // Writing to a String can't fail
+    write_tag(&mut tag, val).unwrap();
+    tag
+}
+
+/// Write the string form of an encoded tag, as `decode_tag` returns it,
+/// without allocating.
+pub fn write_tag<W: fmt::Write>(out: &mut W, val: u64) -> fmt::Result {
+    let language: u64 = (val & LANGUAGE_MASK) >> LANGUAGE_SHIFT;
+    if language == 0 {
+        out.write_str("und")?;
+    } else {
+        write_subtag(out, language, SubtagCase::Lower)?;
+    }
+    let extlang: u64 = (val & EXTLANG_MASK) >> EXTLANG_SHIFT;
+    if extlang != 0 {
+        out.write_char('-')?;
+        write_subtag(out, extlang, SubtagCase::Lower)?;
+    }
+    if val & PROTO_MASK != 0 {
+        out.write_str("-pro")?;
+    }
+    let script: u64 = (val & SCRIPT_MASK) >> SCRIPT_SHIFT;
+    if script != 0 {
+        out.write_char('-')?;
+        write_subtag(out, script, SubtagCase::Title)?;
     }
-    match decode_script(val) {
-        Some(script) => parts.push(script),
-        None => {}
+    let region: u64 = val & REGION_MASK;
+    if region != 0 {
+        out.write_char('-')?;
+        write_subtag(out, region, SubtagCase::Upper)?;
+    }
+    Ok(())
+}
+
+/// Encode a language subtag on its own, such as "zh", in the position it
+/// would have in a complete tag. "und" encodes as 0. This doesn't allocate,
+/// so it's also a cheap way to compare a subtag to an encoded value.
+pub fn encode_language(subtag: &str) -> Result<u64, LanguageCodeError> {
+    check_subtag_characters(subtag)?;
+    if subtag.eq_ignore_ascii_case("und") {
+        Ok(EMPTY_CODE)
+    } else if is_language(subtag) {
+        Ok(encode_subtag(subtag, 3) << LANGUAGE_SHIFT)
+    } else {
+        Err(LanguageCodeError::SubtagFormatError(subtag.to_string()))
+    }
+}
+
+/// Encode a script subtag on its own, such as "Hant", in the position it
+/// would have in a complete tag. "Zzzz" encodes as 0.
+pub fn encode_script(subtag: &str) -> Result<u64, LanguageCodeError> {
+    check_subtag_characters(subtag)?;
+    if subtag.eq_ignore_ascii_case("zzzz") {
+        Ok(EMPTY_CODE)
+    } else if is_script(subtag) && is_alphabetic(subtag) {
+        Ok(encode_subtag(subtag, 4) << SCRIPT_SHIFT)
+    } else {
+        Err(LanguageCodeError::SubtagFormatError(subtag.to_string()))
     }
-    match decode_region(val) {
-        Some(region) => parts.push(region),
-        None => {}
+}
+
+/// Encode a region subtag on its own, such as "TW" or "419", in the position
+/// it would have in a complete tag. "ZZ" encodes as 0.
+pub fn encode_region(subtag: &str) -> Result<u64, LanguageCodeError> {
+    check_subtag_characters(subtag)?;
+    if subtag.eq_ignore_ascii_case("zz") {
+        Ok(EMPTY_CODE)
+    } else if is_region(subtag) {
+        Ok(encode_subtag(subtag, 2))
+    } else {
+        Err(LanguageCodeError::SubtagFormatError(subtag.to_string()))
     }
-    parts.join("-")
 }
 
 pub fn update_code(old_val: u64, new_val: u64) -> u64 {
@@ -232,6 +456,29 @@ fn check_characters(subtag: &str) -> bool {
     subtag.bytes().all(|b| (b >= 0x30 && b <= 0x39) || (b >= 0x61 && b <= 0x7a))
 }
 
+fn is_alphabetic(subtag: &str) -> bool {
+    subtag.bytes().all(|b| b.is_ascii_alphabetic())
+}
+
+/// Reject input that contains any non-ASCII character, reporting the first
+/// such character.
+fn check_ascii(tag: &str) -> Result<(), LanguageCodeError> {
+    match tag.chars().find(|ch| !ch.is_ascii()) {
+        Some(ch) => Err(LanguageCodeError::NonAsciiInput(ch)),
+        None => Ok(()),
+    }
+}
+
+/// Check the characters of a single subtag, in either case.
+fn check_subtag_characters(subtag: &str) -> Result<(), LanguageCodeError> {
+    check_ascii(subtag)?;
+    if subtag.bytes().all(|b| b.is_ascii_alphanumeric()) {
+        Ok(())
+    } else {
+        Err(LanguageCodeError::InvalidCharacter(subtag.to_string()))
+    }
+}
+
 fn is_extension(subtag: &str) -> bool {
     subtag == "u" || subtag == "x"
 }
@@ -246,14 +493,19 @@ fn is_variant(subtag: &str) -> bool {
     }
 }
 
+/// A region is either two letters or exactly three digits.
 fn is_region(subtag: &str) -> bool {
-    let length = subtag.len();
-    match subtag.chars().nth(0) {
-        Some(ch) => (ch.is_digit(10) && length == 3) || length == 2,
-        None => false,
+    match subtag.len() {
+        2 => is_alphabetic(subtag),
+        3 => subtag.bytes().all(|b| b.is_ascii_digit()),
+        _ => false,
     }
 }
 
+fn is_language(subtag: &str) -> bool {
+    (subtag.len() == 2 || subtag.len() == 3) && is_alphabetic(subtag)
+}
+
 fn is_script(subtag: &str) -> bool {
     subtag.len() == 4
 }
@@ -266,6 +518,18 @@ fn is_extlang(subtag: &str) -> bool {
     }
 }
 
+/// Check whether a subtag has the shape of a region: two letters, such as
+/// "TW", or three digits, such as "419". Case doesn't matter.
+pub fn is_region_subtag(subtag: &str) -> bool {
+    is_region(subtag)
+}
+
+/// Check whether a subtag has the shape of a script: four letters, such as
+/// "Hant". Case doesn't matter.
+pub fn is_script_subtag(subtag: &str) -> bool {
+    is_script(subtag) && is_alphabetic(subtag)
+}
+
 pub fn language_pair_bytes(tag1: u64, tag2: u64) -> [u8; 16] {
     let bytes1: [u8; 8] = unsafe { transmute(tag1.to_be()) };
     let bytes2: [u8; 8] = unsafe { transmute(tag2.to_be()) };
@@ -279,6 +543,15 @@ pub fn language_pair_bytes(tag1: u64, tag2: u64) -> [u8; 16] {
     bytes
 }
 
+/// Split the result of `language_pair_bytes` back into its two tags.
+pub fn split_pair_bytes(bytes: &[u8; 16]) -> (u64, u64) {
+    let mut bytes1: [u8; 8] = [0; 8];
+    let mut bytes2: [u8; 8] = [0; 8];
+    bytes1.copy_from_slice(&bytes[..8]);
+    bytes2.copy_from_slice(&bytes[8..]);
+    (u64::from_be_bytes(bytes1), u64::from_be_bytes(bytes2))
+}
+
 
 #[cfg(test)]
 mod tests {
@@ -307,4 +580,136 @@ mod tests {
         round_trip("ine-pro");
         round_trip("roa-opt-pro");
     }
+
+    #[test]
+    fn test_decode_matches_parts() {
+        let tags = ["zh-Hant-TW", "en", "und", "pt-BR", "und-Vaii", "es-419", "ine-pro",
+                    "roa-opt-pro", "sgn-ase-Latn-US", "und-001", "yue-Hant"];
+        for tag in tags.iter() {
+            let val = encode_tag(tag).unwrap();
+            let mut parts: Vec<String> = vec![decode_language(val)];
+            parts.extend(decode_extlang(val));
+            parts.extend(decode_script(val));
+            parts.extend(decode_region(val));
+            assert_eq!(decode_tag(val), parts.join("-"));
+            assert_eq!(&decode_tag(val), tag);
+        }
+    }
+
+    #[test]
+    fn test_numeric_region() {
+        round_trip("und-001");
+        round_trip("es-005");
+        round_trip("es-419");
+        assert_eq!(decode_region(encode_tag("und-001").unwrap()), Some("001".to_string()));
+        assert_eq!(encode_tag("es-41"),
+                   Err(LanguageCodeError::SubtagFormatError("es-41".to_string())));
+        assert_eq!(encode_tag("und-5"),
+                   Err(LanguageCodeError::SubtagFormatError("und-5".to_string())));
+        assert_eq!(encode_tag("es-4a1"),
+                   Err(LanguageCodeError::SubtagFormatError("es-4a1".to_string())));
+        assert!(encode_region("41").is_err());
+        assert!(is_region_subtag("419"));
+        assert!(is_region_subtag("tw"));
+        assert!(!is_region_subtag("41"));
+        assert!(!is_region_subtag("4a1"));
+    }
+
+    #[test]
+    fn test_long_language() {
+        assert_eq!(encode_tag("english"),
+                   Err(LanguageCodeError::SubtagFormatError("english".to_string())));
+        assert_eq!(encode_tag("abcd-US"),
+                   Err(LanguageCodeError::SubtagFormatError("abcd-us".to_string())));
+        assert!(encode_language("abcd").is_err());
+    }
+
+    #[test]
+    fn test_non_ascii() {
+        assert_eq!(encode_tag("frç"), Err(LanguageCodeError::NonAsciiInput('ç')));
+        assert_eq!(encode_tag("日本語"), Err(LanguageCodeError::NonAsciiInput('日')));
+        assert_eq!(encode_tag("en-GB!"),
+                   Err(LanguageCodeError::InvalidCharacter("en-gb!".to_string())));
+        assert_eq!(encode_region("ÅX"), Err(LanguageCodeError::NonAsciiInput('Å')));
+    }
+
+    #[test]
+    fn test_root() {
+        assert_eq!(encode_tag("root"), Ok(EMPTY_CODE));
+        assert_eq!(encode_tag("Root"), Ok(EMPTY_CODE));
+        assert_eq!(decode_tag(encode_tag("root").unwrap()), "und");
+    }
+
+    #[test]
+    fn test_pair_bytes() {
+        let tag1 = encode_tag("en-Latn-US").unwrap();
+        let tag2 = encode_tag("en-GB").unwrap();
+        let bytes = language_pair_bytes(tag1, tag2);
+        assert_eq!(split_pair_bytes(&bytes), (tag1, tag2));
+    }
+
+    #[test]
+    fn test_layout() {
+        let mut covered: u64 = 0;
+        for &(name, field) in LAYOUT.fields().iter() {
+            assert_eq!(covered & field.mask, 0, "{} overlaps another field", name);
+            covered |= field.mask;
+            // Each field starts at its shift and has no gaps
+            let width = field.mask >> field.shift;
+            assert_eq!(width & 1, 1, "{} doesn't start at its shift", name);
+            assert_eq!(width & (width + 1), 0, "{} has a gap", name);
+        }
+        assert_eq!(covered, LANGUAGE_EXT_MASK | SCRIPT_MASK | REGION_MASK);
+
+        let val = encode_tag("zh-cmn-Hant-TW").unwrap();
+        assert_eq!(val & !covered, 0);
+        let language = (val & LAYOUT.language.mask) >> LAYOUT.language.shift;
+        assert_eq!(language, encode_language("zh").unwrap() >> LANGUAGE_SHIFT);
+        let region = (val & LAYOUT.region.mask) >> LAYOUT.region.shift;
+        assert_eq!(region, encode_region("TW").unwrap());
+        let proto = encode_tag("gem-pro").unwrap();
+        assert_eq!((proto & LAYOUT.proto.mask) >> LAYOUT.proto.shift, 1);
+    }
+
+    #[test]
+    fn test_classify() {
+        let roles: Vec<SubtagRole> = classify_subtags("en-Latn-US-x-foo")
+            .into_iter()
+            .map(|(_, role)| role)
+            .collect();
+        assert_eq!(roles,
+                   vec![SubtagRole::Language,
+                        SubtagRole::Script,
+                        SubtagRole::Region,
+                        SubtagRole::PrivateUse,
+                        SubtagRole::PrivateUse]);
+
+        let classified = classify_subtags("de_DE-1901-u-co-phonebk");
+        assert_eq!(classified[0], ("de".to_string(), SubtagRole::Language));
+        assert_eq!(classified[1], ("DE".to_string(), SubtagRole::Region));
+        assert_eq!(classified[2], ("1901".to_string(), SubtagRole::Variant));
+        assert_eq!(classified[3], ("u".to_string(), SubtagRole::Extension));
+        assert_eq!(classified[5], ("phonebk".to_string(), SubtagRole::Extension));
+
+        let classified = classify_subtags("roa-opt-US-Latn");
+        assert_eq!(classified[1].1, SubtagRole::Extlang);
+        assert_eq!(classified[2].1, SubtagRole::Region);
+        assert_eq!(classified[3].1, SubtagRole::Unknown);
+    }
+
+    #[test]
+    fn test_encode_parts() {
+        assert_eq!(encode_language("zh").unwrap() | encode_script("Hant").unwrap() |
+                   encode_region("TW").unwrap(),
+                   encode_tag("zh-Hant-TW").unwrap());
+        assert_eq!(encode_region("419").unwrap(), encode_tag("und-419").unwrap());
+        assert_eq!(encode_language("und"), Ok(EMPTY_CODE));
+        assert_eq!(encode_script("Zzzz"), Ok(EMPTY_CODE));
+        assert_eq!(encode_script("Hants"),
+                   Err(LanguageCodeError::SubtagFormatError("Hants".to_string())));
+        assert_eq!(encode_language("e1"),
+                   Err(LanguageCodeError::SubtagFormatError("e1".to_string())));
+        assert_eq!(encode_region("T-W"),
+                   Err(LanguageCodeError::InvalidCharacter("T-W".to_string())));
+    }
 }